@@ -1,5 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
+use super::interpreter::evaluate;
+use super::liveness::{self, Interval};
 use super::tac;
 
 #[derive(Debug, Clone)]
@@ -7,95 +9,1350 @@ pub struct Register {
     pub name: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterClass {
+    General,
+    Float,
+}
+
 #[derive(Debug)]
 pub struct RegisterSet {
     registers: Vec<Register>,
+    float_registers: Vec<Register>,
     available: Vec<usize>,
-    map: HashMap<usize, usize>,
+    available_float: Vec<usize>,
+    map: HashMap<usize, (RegisterClass, usize)>,
 }
 
 impl RegisterSet {
-    pub fn new(registers: &Vec<Register>) -> Self {
+    pub fn new(registers: &[Register], float_registers: &[Register]) -> Self {
         Self {
             available: (0..registers.len()).collect(),
-            registers: registers.clone(),
+            available_float: (0..float_registers.len()).collect(),
+            registers: registers.to_vec(),
+            float_registers: float_registers.to_vec(),
             map: HashMap::new(),
         }
     }
-    pub fn add_register(&mut self, id: usize) -> Option<Register> {
-        let index = self.available.pop()?;
-        self.map.insert(id, index);
-        Some(self.registers[index].clone())
+    fn class(&self, class: RegisterClass) -> &[Register] {
+        match class {
+            RegisterClass::General => &self.registers,
+            RegisterClass::Float => &self.float_registers,
+        }
+    }
+    pub fn add_register(&mut self, id: usize, class: RegisterClass) -> Option<Register> {
+        let index = match class {
+            RegisterClass::General => self.available.pop()?,
+            RegisterClass::Float => self.available_float.pop()?,
+        };
+        self.map.insert(id, (class, index));
+        Some(self.class(class)[index].clone())
     }
     pub fn free_register(&mut self, i: usize) {
-        self.available.push(*self.map.get(&i).unwrap());
+        match *self.map.get(&i).unwrap() {
+            (RegisterClass::General, index) => self.available.push(index),
+            (RegisterClass::Float, index) => self.available_float.push(index),
+        }
         self.map.remove(&i);
     }
-    pub fn get_register(&mut self, id: usize) -> Option<Register> {
-        match self.map.get(&id) {
-            Some(i) => Some((self.registers.get(*i)?).clone()),
-            None => self.add_register(id),
-        }
+    // Releases every register, ready for the next function
+    pub fn reset(&mut self) {
+        self.available = (0..self.registers.len()).collect();
+        self.available_float = (0..self.float_registers.len()).collect();
+        self.map.clear();
     }
 }
 
+// System V integer argument registers, in order
+const ARG_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+// System V floating point argument registers, in order
+const FLOAT_ARG_REGISTERS: [&str; 8] = [
+    "xmm0", "xmm1", "xmm2", "xmm3", "xmm4", "xmm5", "xmm6", "xmm7",
+];
+// Never given to a variable, so it is free for instructions that need a
+// register operand when their variables live on the stack
+const SCRATCH: &str = "r11";
+const FLOAT_SCRATCH: &str = "xmm15";
+
 pub trait Dialect {
     fn compile_address(&mut self, addr: &tac::Address) -> String;
     fn compile_line(&mut self, line: &tac::Line) -> String;
-    fn compile_function(&mut self, name: &str, lines: &Vec<tac::Line>) -> String;
+    fn compile_function(&mut self, name: &str, function: &tac::Function) -> String;
     fn compile_program(&mut self, program: &tac::Program) -> String;
 }
 
+// Merges the intervals of the two sides of a move when the source is last
+// live on the move and the destination first live there, so they can share
+// a register and the move does nothing. Returns the merged intervals, and
+// the variable whose interval each merged variable joined.
+fn coalesce(
+    function: &tac::Function,
+    intervals: &[Interval],
+) -> (Vec<Interval>, HashMap<u64, u64>) {
+    let mut merged: HashMap<u64, Interval> = intervals
+        .iter()
+        .map(|interval| (interval.address.id().unwrap(), *interval))
+        .collect();
+    let mut leaders: HashMap<u64, u64> = HashMap::new();
+    let leader = |leaders: &HashMap<u64, u64>, mut id: u64| {
+        while let Some(next) = leaders.get(&id) {
+            id = *next;
+        }
+        id
+    };
+    for (i, line) in function.body.iter().enumerate() {
+        let tac::Line::Move(source, dest) = line else {
+            continue;
+        };
+        let (Some(source), Some(dest)) = (source.id(), dest.id()) else {
+            continue;
+        };
+        let (source, dest) = (leader(&leaders, source), leader(&leaders, dest));
+        let (a, b) = (merged[&source], merged[&dest]);
+        // A move between integer types converts, so it has to stay
+        let class =
+            |address: &tac::Address| (address.is_float(), float_size(address), address.int());
+        if source != dest && class(&a.address) == class(&b.address) && a.end == i && b.start == i {
+            merged.insert(source, Interval { end: b.end, ..a });
+            merged.remove(&dest);
+            leaders.insert(dest, source);
+        }
+    }
+    let leaders = leaders
+        .keys()
+        .map(|id| (*id, leader(&leaders, *id)))
+        .collect();
+    let mut intervals: Vec<Interval> = merged.into_values().collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.end, interval.address));
+    (intervals, leaders)
+}
+
+// Name of the low `size` bytes of a 64 bit general purpose register
+fn sized_register(name: &str, size: u64) -> String {
+    let numbered = name
+        .strip_prefix('r')
+        .filter(|n| n.starts_with(char::is_numeric));
+    match (numbered, size) {
+        (Some(_), 8) => name.to_string(),
+        (Some(_), 4) => format!("{}d", name),
+        (Some(_), 2) => format!("{}w", name),
+        (Some(_), _) => format!("{}b", name),
+        (None, 8) => name.to_string(),
+        (None, 4) => format!("e{}", &name[1..]),
+        (None, 2) => name[1..].to_string(),
+        (None, _) => match &name[1..] {
+            "ax" | "bx" | "cx" | "dx" => format!("{}l", &name[1..2]),
+            low => format!("{}l", low),
+        },
+    }
+}
+
+fn size_ptr(size: u64) -> &'static str {
+    match size {
+        1 => "BYTE PTR",
+        2 => "WORD PTR",
+        4 => "DWORD PTR",
+        _ => "QWORD PTR",
+    }
+}
+
+fn is_memory(operand: &str) -> bool {
+    operand.ends_with(']')
+}
+
+fn is_immediate(operand: &str) -> bool {
+    operand.parse::<i64>().is_ok()
+}
+
+// Only mov can take an immediate wider than 32 bits
+fn is_wide_immediate(operand: &str) -> bool {
+    operand
+        .parse::<i64>()
+        .is_ok_and(|value| i32::try_from(value).is_err())
+}
+
+// The low `size` bytes of a register or stack slot
+fn sized_operand(operand: &str, size: u64) -> String {
+    match operand.strip_prefix("QWORD PTR") {
+        Some(address) => format!("{}{}", size_ptr(size), address),
+        None => sized_register(operand, size),
+    }
+}
+
+// The low half of an integer operand, for the 32 bit form of an instruction
+fn low_half(operand: &str) -> String {
+    match operand.parse::<i64>() {
+        Ok(value) => (value as i32).to_string(),
+        Err(_) => sized_operand(operand, 4),
+    }
+}
+
+// Instruction extending a value of `size` bytes to the 64 bit register
+fn extension(dest: &str, source: &str, size: u64, signed: bool) -> String {
+    match (size, signed) {
+        (8, _) => format!("mov {}, {}\n", dest, source),
+        (4, true) => format!("movsxd {}, {}\n", dest, source),
+        // Writing the 32 bit register clears the upper half
+        (4, false) => format!("mov {}, {}\n", sized_register(dest, 4), source),
+        (_, true) => format!("movsx {}, {}\n", dest, source),
+        (_, false) => format!("movzx {}, {}\n", dest, source),
+    }
+}
+
+// Whether every value of one integer type, extended to 64 bits, already is
+// the same value extended from another
+fn keeps(from: tac::Int, to: tac::Int) -> bool {
+    from == to || to.size >= 8 || (from.size < to.size && (to.signed || !from.signed))
+}
+
+// A floating point operand read as a value of `size` bytes. Registers are
+// named the same whatever the size.
+fn float_operand(operand: &str, size: u64) -> String {
+    match operand.strip_prefix("QWORD PTR") {
+        Some(address) => format!("{}{}", size_ptr(size), address),
+        None => operand.to_string(),
+    }
+}
+
+fn float_size(addr: &tac::Address) -> u64 {
+    match addr {
+        tac::Address::FloatConstant(_, size) | tac::Address::FloatVariable(_, size) => *size,
+        _ => 8,
+    }
+}
+
+// Suffix of the SSE instructions for scalars of `size` bytes
+fn suffix(size: u64) -> &'static str {
+    match size {
+        4 => "ss",
+        _ => "sd",
+    }
+}
+
+fn data_directive(size: u64) -> &'static str {
+    match size {
+        1 => ".byte",
+        2 => ".value",
+        4 => ".long",
+        _ => ".quad",
+    }
+}
+
 pub struct GCC {
     register_set: RegisterSet,
+    globals: HashMap<String, (u64, bool)>, // Size and signedness of every global symbol
+    registers: HashMap<u64, Register>,     // Register of each variable that got one
+    slots: HashMap<u64, u64>, // Frame offset of each variable that did not get a register
+    stack_slots: HashMap<u64, u64>, // Frame offset of each stack slot of the TAC
+    live: Vec<HashSet<u64>>,  // Variables live across each line of the function
+    line: usize,              // Index of the line being compiled
+    float_constants: Vec<u64>, // SSE has no immediates, so constants are read from .rodata
+    label_counter: u64,
+    function: String, // Name of the function being compiled, which TAC labels are local to
+    symbols: BTreeSet<String>, // Symbols referenced, by the names symbol() gives them
+    tail_calls: bool, // Whether returned calls jump to the callee
 }
 impl GCC {
     pub fn new(register_set: RegisterSet) -> Self {
-        Self { register_set }
+        Self {
+            register_set,
+            globals: HashMap::new(),
+            registers: HashMap::new(),
+            slots: HashMap::new(),
+            stack_slots: HashMap::new(),
+            live: vec![],
+            line: 0,
+            float_constants: vec![],
+            label_counter: 0,
+            function: String::new(),
+            symbols: BTreeSet::new(),
+            tail_calls: false,
+        }
+    }
+    // Lets calls whose result is returned right away jump to the callee,
+    // which the tail pass switches on
+    pub fn tail_calls(&mut self) {
+        self.tail_calls = true;
+    }
+    fn label(&mut self) -> String {
+        self.label_counter += 1;
+        format!(".L{}", self.label_counter)
+    }
+    // Assembly name of a TAC label. Label numbers never contain '_', so the
+    // name cannot clash with a label of another function.
+    fn tac_label(&self, label: u64) -> String {
+        format!(".L{}_{}", self.function, label)
+    }
+    // Linear scan over the live intervals. A variable gets a register no
+    // variable with an overlapping interval holds, or else a stack slot.
+    // Variables coalesced into one interval share it.
+    fn allocate(&mut self, function: &tac::Function) {
+        self.register_set.reset();
+        self.registers.clear();
+        self.slots.clear();
+        self.stack_slots.clear();
+        let liveness = liveness::analyze(function);
+        let (intervals, leaders) = coalesce(function, &liveness.intervals);
+        let mut active: Vec<Interval> = vec![];
+        for interval in intervals.iter() {
+            active.retain(|other| {
+                let overlaps = other.overlaps(interval);
+                if !overlaps && let Some(id) = other.address.id() {
+                    self.register_set.free_register(id as usize);
+                }
+                overlaps
+            });
+            let id = interval.address.id().unwrap();
+            let class = match interval.address.is_float() {
+                true => RegisterClass::Float,
+                false => RegisterClass::General,
+            };
+            match self.register_set.add_register(id as usize, class) {
+                Some(register) => {
+                    self.registers.insert(id, register);
+                    active.push(*interval);
+                }
+                // Out of registers, so the variable lives in the frame
+                None => {
+                    self.slot(id);
+                }
+            }
+        }
+        for (id, leader) in leaders {
+            if let Some(register) = self.registers.get(&leader) {
+                self.registers.insert(id, register.clone());
+            } else if let Some(offset) = self.slots.get(&leader) {
+                self.slots.insert(id, *offset);
+            }
+        }
+        // Stack slots go below the slots of the variables
+        let deepest = self.slots.values().max().copied().unwrap_or(0);
+        for (i, id) in function.slots().into_iter().enumerate() {
+            self.stack_slots.insert(id, deepest + 8 * (i as u64 + 1));
+        }
+        // Only a variable live on both sides of a line lives across it
+        self.live = liveness
+            .before
+            .iter()
+            .zip(liveness.after.iter())
+            .map(|(before, after)| before.intersection(after).copied().collect())
+            .collect();
+    }
+    fn slot(&mut self, id: u64) -> String {
+        let next = 8 * (self.slots.len() as u64 + 1);
+        let offset = *self.slots.entry(id).or_insert(next);
+        format!("QWORD PTR [rbp - {}]", offset)
+    }
+    // A name for a symbol that GAS cannot misread. In Intel syntax it takes
+    // names like rax and lt for registers and operators wherever they are,
+    // so every reference goes through a local name set to the symbol in
+    // AT&T syntax at the top of the file.
+    fn symbol(&mut self, symbol: &tac::Address) -> String {
+        let tac::Address::Global(name) = symbol else {
+            unreachable!()
+        };
+        self.symbols.insert(name.to_string());
+        format!(".Lsym.{}", name)
+    }
+    // Size and signedness of a global symbol
+    fn global(&self, symbol: &tac::Address) -> (u64, bool) {
+        match symbol {
+            tac::Address::Global(name) => self.globals[*name],
+            _ => unreachable!(),
+        }
+    }
+    fn float_constant(&mut self, bits: u64) -> String {
+        let index = match self.float_constants.iter().position(|b| *b == bits) {
+            Some(index) => index,
+            None => {
+                self.float_constants.push(bits);
+                self.float_constants.len() - 1
+            }
+        };
+        format!("QWORD PTR [rip + .LF{}]", index)
+    }
+    fn compile_global(&mut self, global: &tac::Global) -> String {
+        self.globals
+            .insert(global.name.clone(), (global.size, global.signed));
+        let value = match global.value {
+            Some(value) => value,
+            None => return String::new(), // Defined in another object file
+        };
+        let section = if global.constant {
+            ".section .rodata"
+        } else if value == 0 {
+            ".bss"
+        } else {
+            ".data"
+        };
+        let mut out = format!("{}\n", section);
+        if !global.internal {
+            out += &format!(".global {}\n", global.name);
+        }
+        out += &format!(".balign {}\n{}:\n", global.size, global.name);
+        if section == ".bss" {
+            out += &format!(".zero {}\n", global.size);
+        } else {
+            out += &format!("{} {}\n", data_directive(global.size), value as i64);
+        }
+        out
+    }
+    // Two operand instruction, going through the scratch register when x86
+    // does not allow the combination of operands
+    fn binary(&mut self, instruction: &str, dest: &tac::Address, source: &tac::Address) -> String {
+        if dest.is_float() {
+            return self.float_binary(instruction, dest, source);
+        }
+        let dest = self.compile_address(dest);
+        let source = self.compile_address(source);
+        // Coalesced variables share a register, so their moves do nothing
+        if instruction == "mov" && dest == source {
+            return String::new();
+        }
+        let wide = is_wide_immediate(&source) && (instruction != "mov" || is_memory(&dest));
+        if wide || (is_memory(&dest) && is_memory(&source)) {
+            return format!(
+                "mov {}, {}\n{} {}, {}\n",
+                SCRATCH, source, instruction, dest, SCRATCH
+            );
+        }
+        format!("{} {}, {}\n", instruction, dest, source)
+    }
+    // An integer instruction for the type of the destination. A 32 bit
+    // register has its own form, and other narrow results are extended
+    // again afterwards.
+    fn arithmetic(
+        &mut self,
+        instruction: &str,
+        dest: &tac::Address,
+        source: &tac::Address,
+    ) -> String {
+        let Some(int) = dest.int() else {
+            return self.float_binary(instruction, dest, source);
+        };
+        // Bitwise operations keep two values of one type extended
+        if matches!(instruction, "and" | "or" | "xor") && source.int() == Some(int) {
+            return self.binary(instruction, dest, source);
+        }
+        let register = self.compile_address(dest);
+        if int.size == 4 && !is_memory(&register) {
+            let source = low_half(&self.compile_address(source));
+            let out = format!(
+                "{} {}, {}\n",
+                instruction,
+                sized_register(&register, 4),
+                source
+            );
+            return match int.signed {
+                true => out + &self.wrap(dest),
+                false => out,
+            };
+        }
+        let out = match instruction {
+            "imul" => self.multiply(dest, source),
+            _ => self.binary(instruction, dest, source),
+        };
+        out + &self.wrap(dest)
+    }
+    // A one operand integer instruction for the type of its operand
+    fn unary(&mut self, instruction: &str, dest: &tac::Address) -> String {
+        let int = dest.int().unwrap();
+        let operand = self.compile_address(dest);
+        if int.size == 4 && !is_memory(&operand) {
+            let out = format!("{} {}\n", instruction, sized_register(&operand, 4));
+            return match int.signed {
+                true => out + &self.wrap(dest),
+                false => out,
+            };
+        }
+        format!("{} {}\n", instruction, operand) + &self.wrap(dest)
+    }
+    // Extends a narrow integer variable again from its low bytes, after an
+    // instruction that worked on all 64 bits
+    fn wrap(&mut self, dest: &tac::Address) -> String {
+        match dest.int() {
+            Some(int) if int.size < 8 => self.extend(dest, dest, int.size, int.signed),
+            _ => String::new(),
+        }
+    }
+    // Copies a value, converting it to the type of the destination
+    fn mov(&mut self, dest: &tac::Address, source: &tac::Address) -> String {
+        if let tac::Address::Slot(_) = source {
+            let slot = self.compile_address(source);
+            return self.write_register(dest, |dest| format!("lea {}, [{}]\n", dest, slot));
+        }
+        let (Some(from), Some(to)) = (source.int(), dest.int()) else {
+            return self.binary("mov", dest, source);
+        };
+        if keeps(from, to) {
+            return self.binary("mov", dest, source);
+        }
+        match source {
+            tac::Address::Constant(value, _) => {
+                self.binary("mov", dest, &tac::Address::Constant(to.extend(*value), to))
+            }
+            _ => self.extend(source, dest, to.size, to.signed),
+        }
+    }
+    // Reads an integer of type `from` at a memory address into a variable,
+    // converting it to the variable's type. Only the low bytes matter for a
+    // type no wider than the one in memory.
+    fn read(&mut self, dest: &tac::Address, address: &str, from: tac::Int) -> String {
+        let to = dest.int().unwrap();
+        let read = match to.size <= from.size {
+            true => to,
+            false => from,
+        };
+        let source = format!("{} {}", size_ptr(read.size), address);
+        let out = self.write_register(dest, |dest| {
+            extension(dest, &source, read.size, read.signed)
+        });
+        match keeps(read, to) {
+            true => out,
+            false => out + &self.wrap(dest),
+        }
+    }
+    // The scalar SSE form of a two operand instruction, whose destination
+    // has to be a register
+    fn float_binary(
+        &mut self,
+        instruction: &str,
+        dest: &tac::Address,
+        source: &tac::Address,
+    ) -> String {
+        let size = float_size(dest);
+        let mov = format!("mov{}", suffix(size));
+        let instruction = match instruction {
+            "mov" => mov.clone(),
+            other => format!("{}{}", other, suffix(size)),
+        };
+        let dest = float_operand(&self.compile_address(dest), size);
+        let source = float_operand(&self.compile_address(source), size);
+        if instruction == mov && dest == source {
+            return String::new();
+        }
+        if !is_memory(&dest) {
+            return format!("{} {}, {}\n", instruction, dest, source);
+        }
+        if instruction == mov && !is_memory(&source) {
+            return format!("{} {}, {}\n", mov, dest, source);
+        }
+        let load = match instruction == mov {
+            true => format!("{} {}, {}\n", mov, FLOAT_SCRATCH, source),
+            false => format!(
+                "{} {}, {}\n{} {}, {}\n",
+                mov, FLOAT_SCRATCH, dest, instruction, FLOAT_SCRATCH, source
+            ),
+        };
+        format!("{}{} {}, {}\n", load, mov, dest, FLOAT_SCRATCH)
+    }
+    fn multiply(&mut self, dest: &tac::Address, source: &tac::Address) -> String {
+        let dest = self.compile_address(dest);
+        let source = self.compile_address(source);
+        if is_memory(&dest) {
+            // Multiplication is commutative, so the scratch register can
+            // start out with either operand
+            return format!(
+                "mov {}, {}\nimul {}, {}\nmov {}, {}\n",
+                SCRATCH, source, SCRATCH, dest, dest, SCRATCH
+            );
+        }
+        if is_wide_immediate(&source) {
+            return format!("mov {}, {}\nimul {}, {}\n", SCRATCH, source, dest, SCRATCH);
+        }
+        format!("imul {}, {}\n", dest, source)
+    }
+    // Division takes its dividend from rdx:rax and leaves the quotient in rax
+    // and the remainder in rdx, so both are saved around it unless they
+    // receive the result
+    fn divide(
+        &mut self,
+        dest: &tac::Address,
+        source: &tac::Address,
+        signed: bool,
+        remainder: bool,
+    ) -> String {
+        let wrap = self.wrap(dest);
+        let dest = self.compile_address(dest);
+        let source = self.compile_address(source);
+        let saved: Vec<&str> = ["rax", "rdx"]
+            .into_iter()
+            .filter(|register| *register != dest)
+            .collect();
+        let mut out = format!("mov {}, {}\n", SCRATCH, source);
+        for register in saved.iter() {
+            out += &format!("push {}\n", register);
+        }
+        if dest != "rax" {
+            out += &format!("mov rax, {}\n", dest);
+        }
+        out += &match signed {
+            true => format!("cqo\nidiv {}\n", SCRATCH),
+            false => format!("xor edx, edx\ndiv {}\n", SCRATCH),
+        };
+        let result = if remainder { "rdx" } else { "rax" };
+        if dest != result {
+            out += &format!("mov {}, {}\n", dest, result);
+        }
+        for register in saved.iter().rev() {
+            out += &format!("pop {}\n", register);
+        }
+        // The 64 bit quotient of the most negative value by -1 overflows a
+        // narrower type
+        out + &wrap
+    }
+    // A variable shift count has to be in cl, so rcx is saved around the
+    // shift, which is done in the scratch register in case it is the
+    // destination
+    fn shift(&mut self, instruction: &str, dest: &tac::Address, source: &tac::Address) -> String {
+        let operand = self.compile_address(dest);
+        let source = self.compile_address(source);
+        let out = match source.parse::<i64>() {
+            // The processor only looks at the low 6 bits of the count
+            Ok(count) => format!("{} {}, {}\n", instruction, operand, count & 63),
+            Err(_) => {
+                let mut out = format!("mov {}, {}\npush rcx\n", SCRATCH, operand);
+                if source != "rcx" {
+                    out += &format!("mov rcx, {}\n", source);
+                }
+                out + &format!(
+                    "{} {}, cl\npop rcx\nmov {}, {}\n",
+                    instruction, SCRATCH, operand, SCRATCH
+                )
+            }
+        };
+        // Shifting right the way the type is extended keeps it extended
+        match (instruction, dest.int().unwrap().signed) {
+            ("sar", true) | ("shr", false) => out,
+            _ => out + &self.wrap(dest),
+        }
+    }
+    // Sets an integer variable to 0 or 1 from comparing two integers
+    fn compare(&mut self, line: &tac::Line) -> String {
+        let (a, b, c, condition) = match line {
+            tac::Line::Equal(a, b, c) => (a, b, c, "e"),
+            tac::Line::NotEqual(a, b, c) => (a, b, c, "ne"),
+            tac::Line::Less(a, b, c) => (a, b, c, "l"),
+            tac::Line::LessOrEqual(a, b, c) => (a, b, c, "le"),
+            tac::Line::Greater(a, b, c) => (a, b, c, "g"),
+            tac::Line::GreaterOrEqual(a, b, c) => (a, b, c, "ge"),
+            tac::Line::UnsignedLess(a, b, c) => (a, b, c, "b"),
+            tac::Line::UnsignedLessOrEqual(a, b, c) => (a, b, c, "be"),
+            tac::Line::UnsignedGreater(a, b, c) => (a, b, c, "a"),
+            tac::Line::UnsignedGreaterOrEqual(a, b, c) => (a, b, c, "ae"),
+            _ => unreachable!(),
+        };
+        // cmp needs one operand that is not an immediate, so comparisons of
+        // two constants are worked out here
+        if let (tac::Address::Constant(..), tac::Address::Constant(..)) = (a, b) {
+            let value = evaluate(line, |addr| match addr {
+                tac::Address::Constant(value, _) => Ok(*value),
+                _ => unreachable!(),
+            })
+            .unwrap();
+            return format!("mov {}, {}\n", self.compile_address(c), value);
+        }
+        // Two values of one 32 bit type compare the same in their 32 bit
+        // form, except unsigned values in a signed comparison
+        let half = a.int() == b.int()
+            && a.int().is_some_and(|int| {
+                int.size == 4 && (int.signed || !matches!(condition, "l" | "le" | "g" | "ge"))
+            });
+        let mut out = String::new();
+        let mut a = self.compile_address(a);
+        let mut b = self.compile_address(b);
+        if is_wide_immediate(&b) {
+            out += &format!("mov {}, {}\n", SCRATCH, b);
+            b = SCRATCH.to_string();
+        } else if is_immediate(&a) || (is_memory(&a) && is_memory(&b)) {
+            out += &format!("mov {}, {}\n", SCRATCH, a);
+            a = SCRATCH.to_string();
+        }
+        if half {
+            (a, b) = (low_half(&a), low_half(&b));
+        }
+        let c = self.compile_address(c);
+        // mov leaves the flags alone, so the destination can be cleared
+        // after the comparison, when it may be one of the operands
+        out += &format!(
+            "cmp {}, {}\nmov {}, 0\nset{} {}\n",
+            a,
+            b,
+            c,
+            condition,
+            sized_operand(&c, 1)
+        );
+        out
+    }
+    // The address in a register, so it can be used in a memory operand
+    fn pointer(&mut self, addr: &tac::Address) -> (String, String) {
+        let pointer = self.compile_address(addr);
+        if is_memory(&pointer) || is_immediate(&pointer) {
+            return (
+                format!("mov {}, {}\n", SCRATCH, pointer),
+                SCRATCH.to_string(),
+            );
+        }
+        (String::new(), pointer)
+    }
+    fn load(&mut self, pointer: &tac::Address, dest: &tac::Address, size: u64) -> String {
+        let (mut out, pointer) = self.pointer(pointer);
+        let address = format!("[{}]", pointer);
+        out += &match dest.is_float() {
+            true => self.write_float_register(dest, |dest| {
+                format!(
+                    "mov{} {}, {} {}\n",
+                    suffix(size),
+                    dest,
+                    size_ptr(size),
+                    address
+                )
+            }),
+            // The bytes are read as unsigned, and converted from there
+            false => self.read(
+                dest,
+                &address,
+                tac::Int {
+                    size,
+                    signed: false,
+                },
+            ),
+        };
+        out
+    }
+    fn store(&mut self, source: &tac::Address, pointer: &tac::Address, size: u64) -> String {
+        let float = source.is_float();
+        let (mut out, pointer) = self.pointer(pointer);
+        let dest = format!("{} [{}]", size_ptr(size), pointer);
+        let mut source = self.compile_address(source);
+        if float {
+            if is_memory(&source) {
+                out += &format!(
+                    "mov{} {}, {}\n",
+                    suffix(size),
+                    FLOAT_SCRATCH,
+                    float_operand(&source, size)
+                );
+                source = FLOAT_SCRATCH.to_string();
+            }
+            out += &format!("mov{} {}, {}\n", suffix(size), dest, source);
+            return out;
+        }
+        if is_memory(&source) || is_wide_immediate(&source) {
+            if pointer == SCRATCH {
+                // The scratch register holds the address, so rax is
+                // borrowed for the value instead
+                return out
+                    + &format!(
+                        "push rax\nmov rax, {}\nmov {}, {}\npop rax\n",
+                        source,
+                        dest,
+                        sized_register("rax", size)
+                    );
+            }
+            out += &format!("mov {}, {}\n", SCRATCH, source);
+            source = SCRATCH.to_string();
+        }
+        if !is_immediate(&source) {
+            source = sized_register(&source, size);
+        }
+        out += &format!("mov {}, {}\n", dest, source);
+        out
+    }
+    // Instructions that can only write a register
+    fn write_register(
+        &mut self,
+        dest: &tac::Address,
+        instruction: impl Fn(&str) -> String,
+    ) -> String {
+        let dest = self.compile_address(dest);
+        if is_memory(&dest) {
+            return format!("{}mov {}, {}\n", instruction(SCRATCH), dest, SCRATCH);
+        }
+        instruction(&dest)
+    }
+    fn write_float_register(
+        &mut self,
+        dest: &tac::Address,
+        instruction: impl Fn(&str) -> String,
+    ) -> String {
+        let size = float_size(dest);
+        let dest = self.compile_address(dest);
+        if is_memory(&dest) {
+            return format!(
+                "{}mov{} {}, {}\n",
+                instruction(FLOAT_SCRATCH),
+                suffix(size),
+                float_operand(&dest, size),
+                FLOAT_SCRATCH
+            );
+        }
+        instruction(&dest)
+    }
+    fn extend(
+        &mut self,
+        source: &tac::Address,
+        dest: &tac::Address,
+        size: u64,
+        signed: bool,
+    ) -> String {
+        let mut out = String::new();
+        let mut source = self.compile_address(source);
+        if is_immediate(&source) {
+            out += &format!("mov {}, {}\n", SCRATCH, source);
+            source = SCRATCH.to_string();
+        }
+        let source = sized_operand(&source, size);
+        out += &self.write_register(dest, |dest| extension(dest, &source, size, signed));
+        out
+    }
+    // Sets an integer variable to 0 or 1 from comparing two floating point
+    // values. ucomis sets the flags like an unsigned comparison, and a NaN
+    // operand sets the parity flag.
+    fn compare_float(&mut self, line: &tac::Line) -> String {
+        let (a, b, c) = match line {
+            // a < b is b > a, which is false for NaN like every other ordering
+            tac::Line::Less(a, b, c) | tac::Line::LessOrEqual(a, b, c) => (b, a, c),
+            tac::Line::Equal(a, b, c)
+            | tac::Line::NotEqual(a, b, c)
+            | tac::Line::Greater(a, b, c)
+            | tac::Line::GreaterOrEqual(a, b, c) => (a, b, c),
+            _ => unreachable!(),
+        };
+        let size = float_size(a);
+        let mut out = String::new();
+        let mut a = float_operand(&self.compile_address(a), size);
+        if is_memory(&a) {
+            out += &format!("mov{} {}, {}\n", suffix(size), FLOAT_SCRATCH, a);
+            a = FLOAT_SCRATCH.to_string();
+        }
+        let b = float_operand(&self.compile_address(b), size);
+        let c = self.compile_address(c);
+        out += &format!("ucomi{} {}, {}\n", suffix(size), a, b);
+        // mov leaves the flags alone, so the destination can be cleared here
+        let set =
+            |condition: &str| format!("mov {}, 0\nset{} {}\n", c, condition, sized_operand(&c, 1));
+        match line {
+            tac::Line::Equal(..) => {
+                out += &format!("mov {}d, 0\nsetnp {}b\n", SCRATCH, SCRATCH);
+                out += &set("e");
+                out += &format!("and {}, {}\n", c, SCRATCH);
+            }
+            tac::Line::NotEqual(..) => {
+                out += &format!("mov {}d, 0\nsetp {}b\n", SCRATCH, SCRATCH);
+                out += &set("ne");
+                out += &format!("or {}, {}\n", c, SCRATCH);
+            }
+            tac::Line::Less(..) | tac::Line::Greater(..) => out += &set("a"),
+            _ => out += &set("ae"),
+        }
+        out
+    }
+    fn int_to_float(&mut self, source: &tac::Address, dest: &tac::Address, signed: bool) -> String {
+        let instruction = format!("cvtsi2{}", suffix(float_size(dest)));
+        let source = self.compile_address(source);
+        let mut out = format!("mov {}, {}\n", SCRATCH, source);
+        if signed {
+            out += &self.write_float_register(dest, |dest| {
+                format!("{} {}, {}\n", instruction, dest, SCRATCH)
+            });
+            return out;
+        }
+        // Values with the top bit set are halved for the signed conversion,
+        // keeping the lowest bit so the result still rounds correctly
+        let (big, done) = (self.label(), self.label());
+        let add = format!("add{}", suffix(float_size(dest)));
+        out += &self.write_float_register(dest, |dest| {
+            format!(
+                "test {s}, {s}\njs {big}\n{i} {d}, {s}\njmp {done}\n{big}:\npush {s}\nshr {s}, 1\n\
+                 and QWORD PTR [rsp], 1\nor {s}, QWORD PTR [rsp]\nadd rsp, 8\n{i} {d}, {s}\n\
+                 {add} {d}, {d}\n{done}:\n",
+                s = SCRATCH,
+                d = dest,
+                i = instruction,
+                big = big,
+                done = done,
+                add = add
+            )
+        });
+        out
+    }
+    fn float_to_int(&mut self, source: &tac::Address, dest: &tac::Address, signed: bool) -> String {
+        let size = float_size(source);
+        let source = float_operand(&self.compile_address(source), size);
+        if signed {
+            let out = self.write_register(dest, |dest| {
+                format!("cvtt{}2si {}, {}\n", suffix(size), dest, source)
+            });
+            return out + &self.wrap(dest);
+        }
+        // Values from 2^63 up do not fit the signed conversion, so 2^63 is
+        // taken off first and its bit set again afterwards
+        let two_63 = self.float_constant(((1u64 << 63) as f64).to_bits());
+        let (big, done) = (self.label(), self.label());
+        let mut out = match size {
+            4 => format!("cvtss2sd {}, {}\n", FLOAT_SCRATCH, source),
+            _ => format!("movsd {}, {}\n", FLOAT_SCRATCH, source),
+        };
+        out += &format!(
+            "ucomisd {x}, {two_63}\njae {big}\ncvttsd2si {s}, {x}\njmp {done}\n{big}:\n\
+             subsd {x}, {two_63}\ncvttsd2si {s}, {x}\nbtc {s}, 63\n{done}:\n",
+            x = FLOAT_SCRATCH,
+            s = SCRATCH,
+            two_63 = two_63,
+            big = big,
+            done = done
+        );
+        let operand = self.compile_address(dest);
+        out += &format!("mov {}, {}\n", operand, SCRATCH);
+        out + &self.wrap(dest)
+    }
+    fn push_address(&mut self, addr: &tac::Address) -> String {
+        match addr {
+            tac::Address::Constant(value, _) if i32::try_from(*value as i64).is_err() => format!(
+                "sub rsp, 8\nmov DWORD PTR [rsp], {}\nmov DWORD PTR [rsp + 4], {}\n",
+                *value as u32,
+                (*value >> 32) as u32
+            ),
+            tac::Address::FloatVariable(..) => match self.compile_address(addr) {
+                operand if is_memory(&operand) => format!("push {}\n", operand),
+                register => format!("sub rsp, 8\nmovsd QWORD PTR [rsp], {}\n", register),
+            },
+            _ => format!("push {}\n", self.compile_address(addr)),
+        }
+    }
+    // Registers of the variables live across the current line, in a fixed
+    // order
+    fn live_registers(&self, float: bool) -> Vec<Register> {
+        let mut registers: Vec<Register> = self.live[self.line]
+            .iter()
+            .filter_map(|id| self.registers.get(id))
+            .filter(|register| register.name.starts_with("xmm") == float)
+            .cloned()
+            .collect();
+        registers.sort_by(|a, b| a.name.cmp(&b.name));
+        registers
+    }
+    // Caller saved registers holding variables live across the call are
+    // pushed around it, and a result in a register is written over its saved
+    // copy.
+    fn compile_call(
+        &mut self,
+        callee: &tac::Address,
+        args: &[tac::Address],
+        dest: &tac::Address,
+    ) -> String {
+        let name = self.symbol(callee);
+        let saved = self.live_registers(false);
+        let saved_float = self.live_registers(true);
+        let result = self.compile_address(dest);
+        // Each argument takes the next register of its class, or else a
+        // stack slot
+        let mut registers = ARG_REGISTERS.iter();
+        let mut float_registers = FLOAT_ARG_REGISTERS.iter();
+        let mut in_registers = vec![];
+        let mut on_stack = vec![];
+        for arg in args {
+            let register = match arg.is_float() {
+                true => float_registers.next(),
+                false => registers.next(),
+            };
+            match register {
+                Some(register) => in_registers.push((register, arg)),
+                None => on_stack.push(arg),
+            }
+        }
+        let mut out = String::new();
+        // The stack has to be 16 byte aligned at the call
+        let padding = (saved.len() + saved_float.len() + on_stack.len()) % 2 == 1;
+        if padding {
+            out += "sub rsp, 8\n";
+        }
+        for register in saved.iter() {
+            out += &format!("push {}\n", register.name);
+        }
+        for register in saved_float.iter() {
+            out += &format!("sub rsp, 8\nmovsd QWORD PTR [rsp], {}\n", register.name);
+        }
+        // Every argument goes through the stack, so none of the argument
+        // registers are overwritten while they may still be read
+        for arg in on_stack.iter().rev() {
+            out += &self.push_address(arg);
+        }
+        for (_, arg) in in_registers.iter().rev() {
+            out += &self.push_address(arg);
+        }
+        for (register, arg) in in_registers.iter() {
+            match arg.is_float() {
+                true => out += &format!("movsd {}, QWORD PTR [rsp]\nadd rsp, 8\n", register),
+                false => out += &format!("pop {}\n", register),
+            }
+        }
+        // al holds the number of vector registers used, for variadic callees
+        let used_float = in_registers
+            .iter()
+            .filter(|(_, arg)| arg.is_float())
+            .count();
+        out += &format!("mov eax, {}\n", used_float);
+        out += &format!("call {}\n", name);
+        if !on_stack.is_empty() {
+            out += &format!("add rsp, {}\n", 8 * on_stack.len());
+        }
+        let (mov, returned) = match dest.is_float() {
+            true => ("movsd", "xmm0"),
+            false => ("mov", "rax"),
+        };
+        match saved
+            .iter()
+            .chain(saved_float.iter())
+            .rev()
+            .position(|register| register.name == result)
+        {
+            Some(slot) => out += &format!("{} QWORD PTR [rsp + {}], {}\n", mov, 8 * slot, returned),
+            None => out += &format!("{} {}, {}\n", mov, result, returned),
+        }
+        for register in saved_float.iter().rev() {
+            out += &format!("movsd {}, QWORD PTR [rsp]\nadd rsp, 8\n", register.name);
+        }
+        for register in saved.iter().rev() {
+            out += &format!("pop {}\n", register.name);
+        }
+        if padding {
+            out += "add rsp, 8\n";
+        }
+        // Only the low bytes of a narrow result are defined by the ABI
+        out + &self.wrap(dest)
+    }
+    // The arguments go where the callee looks for them and the frame is
+    // left before the jump. Arguments on the stack would have to go in the
+    // caller's own incoming argument area, which may be too small, so calls
+    // with them are left alone.
+    fn compile_tail_call(
+        &mut self,
+        callee: &tac::Address,
+        args: &[tac::Address],
+    ) -> Option<String> {
+        let name = self.symbol(callee);
+        let mut registers = ARG_REGISTERS.iter();
+        let mut float_registers = FLOAT_ARG_REGISTERS.iter();
+        let mut in_registers = vec![];
+        for arg in args {
+            let register = match arg.is_float() {
+                true => float_registers.next(),
+                false => registers.next(),
+            }?;
+            in_registers.push((register, arg));
+        }
+        let mut out = String::new();
+        // As for calls, every argument goes through the stack first
+        for (_, arg) in in_registers.iter().rev() {
+            out += &self.push_address(arg);
+        }
+        for (register, arg) in in_registers.iter() {
+            match arg.is_float() {
+                true => out += &format!("movsd {}, QWORD PTR [rsp]\nadd rsp, 8\n", register),
+                false => out += &format!("pop {}\n", register),
+            }
+        }
+        let used_float = in_registers
+            .iter()
+            .filter(|(_, arg)| arg.is_float())
+            .count();
+        out += &format!("mov rsp, rbp\npop rbp\nmov eax, {}\n", used_float);
+        out += &format!("jmp {}\n", name);
+        Some(out)
     }
 }
+
 impl Dialect for GCC {
     fn compile_address(&mut self, addr: &tac::Address) -> String {
         match addr {
-            tac::Address::Constant(value) => {
-                format!("{}", value)
+            tac::Address::Constant(value, _) => {
+                format!("{}", *value as i64)
             }
-            tac::Address::Variable(id) => {
-                format!("{:?}", self.register_set.get_register(*id as usize))
+            tac::Address::FloatConstant(bits, _) => self.float_constant(*bits),
+            tac::Address::Variable(id, _) | tac::Address::FloatVariable(id, _) => {
+                match self.registers.get(id) {
+                    Some(register) => register.name.clone(),
+                    None => self.slot(*id),
+                }
             }
+            tac::Address::Global(_) => self.symbol(addr),
+            // Only used in brackets, or by lea
+            tac::Address::Slot(id) => format!("rbp - {}", self.stack_slots[id]),
+            tac::Address::Label(label) => self.tac_label(*label),
         }
     }
 
     fn compile_line(&mut self, line: &tac::Line) -> String {
         match line {
-            tac::Line::Add(a, b) => {
-                format!("add {}, {}\n", a, b)
-            }
-            tac::Line::Subtract(a, b) => {
-                format!("sub {}, {}\n", a, b)
+            tac::Line::Add(a, b) => self.arithmetic("add", a, b),
+            tac::Line::Subtract(a, b) => self.arithmetic("sub", a, b),
+            tac::Line::Multiply(a, b) if a.is_float() => self.float_binary("mul", a, b),
+            tac::Line::Multiply(a, b) => self.arithmetic("imul", a, b),
+            tac::Line::Divide(a, b) if a.is_float() => self.float_binary("div", a, b),
+            tac::Line::Divide(a, b) => self.divide(a, b, true, false),
+            tac::Line::UnsignedDivide(a, b) => self.divide(a, b, false, false),
+            tac::Line::Remainder(a, b) => self.divide(a, b, true, true),
+            tac::Line::UnsignedRemainder(a, b) => self.divide(a, b, false, true),
+            tac::Line::And(a, b) => self.arithmetic("and", a, b),
+            tac::Line::Or(a, b) => self.arithmetic("or", a, b),
+            tac::Line::Xor(a, b) => self.arithmetic("xor", a, b),
+            tac::Line::ShiftLeft(a, b) => self.shift("shl", a, b),
+            tac::Line::ShiftRight(a, b) => self.shift("sar", a, b),
+            tac::Line::UnsignedShiftRight(a, b) => self.shift("shr", a, b),
+            tac::Line::Negate(a) => self.unary("neg", a),
+            tac::Line::Complement(a) => self.unary("not", a),
+            tac::Line::Not(a) => {
+                let a = self.compile_address(a);
+                format!(
+                    "cmp {}, 0\nmov {}, 0\nsete {}\n",
+                    a,
+                    a,
+                    sized_operand(&a, 1)
+                )
             }
             tac::Line::Return(a) => {
-                format!("mov {}, %rax\nret\n", a)
+                let value = self.compile_address(a);
+                let mov = match a.is_float() {
+                    true => format!(
+                        "mov{} xmm0, {}",
+                        suffix(float_size(a)),
+                        float_operand(&value, float_size(a))
+                    ),
+                    false => format!("mov rax, {}", value),
+                };
+                format!("{}\nmov rsp, rbp\npop rbp\nret\n", mov)
             }
-            tac::Line::Move(source, dest) => {
-                format!("mov {}, {}\n", dest, source)
+            tac::Line::Move(source, dest) => self.mov(dest, source),
+            tac::Line::LoadGlobal(symbol, dest) => {
+                let (size, signed) = self.global(symbol);
+                let address = format!("[rip + {}]", self.symbol(symbol));
+                if dest.is_float() {
+                    return self.write_float_register(dest, |dest| {
+                        format!(
+                            "mov{} {}, {} {}\n",
+                            suffix(size),
+                            dest,
+                            size_ptr(size),
+                            address
+                        )
+                    });
+                }
+                self.read(dest, &address, tac::Int { size, signed })
+            }
+            tac::Line::StoreGlobal(source, symbol) => {
+                let (size, _) = self.global(symbol);
+                let global = format!("{} [rip + {}]", size_ptr(size), self.symbol(symbol));
+                let mut out = String::new();
+                let mut source = self.compile_address(source);
+                if line.addresses()[0].is_float() {
+                    if is_memory(&source) {
+                        out += &format!(
+                            "mov{} {}, {}\n",
+                            suffix(size),
+                            FLOAT_SCRATCH,
+                            float_operand(&source, size)
+                        );
+                        source = FLOAT_SCRATCH.to_string();
+                    }
+                    out += &format!("mov{} {}, {}\n", suffix(size), global, source);
+                    return out;
+                }
+                if is_memory(&source) || is_wide_immediate(&source) {
+                    out += &format!("mov {}, {}\n", SCRATCH, source);
+                    source = SCRATCH.to_string();
+                }
+                if !is_immediate(&source) {
+                    source = sized_register(&source, size);
+                }
+                out += &format!("mov {}, {}\n", global, source);
+                out
+            }
+            tac::Line::SignExtend(source, dest, size)
+            | tac::Line::ZeroExtend(source, dest, size) => {
+                let signed = matches!(line, tac::Line::SignExtend(..));
+                let out = self.extend(source, dest, *size, signed);
+                match keeps(
+                    tac::Int {
+                        size: *size,
+                        signed,
+                    },
+                    dest.int().unwrap(),
+                ) {
+                    true => out,
+                    false => out + &self.wrap(dest),
+                }
+            }
+            tac::Line::Call(name, args, dest) => self.compile_call(name, args, dest),
+            tac::Line::Equal(a, ..)
+            | tac::Line::NotEqual(a, ..)
+            | tac::Line::Less(a, ..)
+            | tac::Line::LessOrEqual(a, ..)
+            | tac::Line::Greater(a, ..)
+            | tac::Line::GreaterOrEqual(a, ..)
+                if a.is_float() =>
+            {
+                self.compare_float(line)
+            }
+            tac::Line::Equal(..)
+            | tac::Line::NotEqual(..)
+            | tac::Line::Less(..)
+            | tac::Line::LessOrEqual(..)
+            | tac::Line::Greater(..)
+            | tac::Line::GreaterOrEqual(..)
+            | tac::Line::UnsignedLess(..)
+            | tac::Line::UnsignedLessOrEqual(..)
+            | tac::Line::UnsignedGreater(..)
+            | tac::Line::UnsignedGreaterOrEqual(..) => self.compare(line),
+            tac::Line::Label(label) => format!("{}:\n", self.compile_address(label)),
+            tac::Line::Jump(label) => format!("jmp {}\n", self.compile_address(label)),
+            tac::Line::JumpIfZero(a, label) | tac::Line::JumpIfNotZero(a, label) => {
+                let (a, label) = (self.compile_address(a), self.compile_address(label));
+                let zero = matches!(line, tac::Line::JumpIfZero(..));
+                match a.parse::<i64>() {
+                    // A constant condition is known now
+                    Ok(value) if (value == 0) == zero => format!("jmp {}\n", label),
+                    Ok(_) => String::new(),
+                    Err(_) => {
+                        let jump = if zero { "je" } else { "jne" };
+                        format!("cmp {}, 0\n{} {}\n", a, jump, label)
+                    }
+                }
+            }
+            tac::Line::Load(pointer, dest, size) => self.load(pointer, dest, *size),
+            tac::Line::Store(source, pointer, size) => self.store(source, pointer, *size),
+            tac::Line::IntToFloat(source, dest) => self.int_to_float(source, dest, true),
+            tac::Line::UnsignedToFloat(source, dest) => self.int_to_float(source, dest, false),
+            tac::Line::FloatToInt(source, dest) => self.float_to_int(source, dest, true),
+            tac::Line::FloatToUnsigned(source, dest) => self.float_to_int(source, dest, false),
+            tac::Line::ConvertFloat(source, dest) => {
+                let (from, to) = (float_size(source), float_size(dest));
+                let source = float_operand(&self.compile_address(source), from);
+                self.write_float_register(dest, |dest| {
+                    format!("cvt{}2{} {}, {}\n", suffix(from), suffix(to), dest, source)
+                })
             }
         }
     }
 
-    fn compile_function(&mut self, name: &str, lines: &Vec<tac::Line>) -> String {
-        let mut out = format!(".global {name}\n{name}:\n");
-        for line in lines {
-            out += &self.compile_line(line);
+    fn compile_function(&mut self, name: &str, function: &tac::Function) -> String {
+        // Give every variable its register or stack slot up front, so the
+        // size of the frame is known
+        self.function = name.to_string();
+        self.allocate(function);
+
+        let mut out = String::new();
+        if !function.internal {
+            out += &format!(".global {name}\n");
+        }
+        out += &format!("{name}:\npush rbp\nmov rbp, rsp\n");
+        // Coalesced variables share slots, so the deepest slot sets the size
+        let frame = self
+            .slots
+            .values()
+            .chain(self.stack_slots.values())
+            .max()
+            .copied()
+            .unwrap_or(0)
+            .next_multiple_of(16);
+        if frame > 0 {
+            out += &format!("sub rsp, {}\n", frame);
+        }
+        // Parameters arrive in the argument registers of their class, and
+        // then on the stack
+        let mut registers = ARG_REGISTERS.iter();
+        let mut float_registers = FLOAT_ARG_REGISTERS.iter();
+        let mut in_registers = vec![];
+        let mut on_stack = vec![];
+        for param in function.params.iter() {
+            let register = match param.is_float() {
+                true => float_registers.next(),
+                false => registers.next(),
+            };
+            match register {
+                Some(register) => in_registers.push((register, param)),
+                None => on_stack.push(param),
+            }
+        }
+        for (register, param) in in_registers.iter() {
+            match param.is_float() {
+                true => out += &format!("sub rsp, 8\nmovsd QWORD PTR [rsp], {}\n", register),
+                false => out += &format!("push {}\n", register),
+            }
+        }
+        for (_, param) in in_registers.iter().rev() {
+            match self.compile_address(param) {
+                var if param.is_float() && !is_memory(&var) => {
+                    out += &format!("movsd {}, QWORD PTR [rsp]\nadd rsp, 8\n", var)
+                }
+                var => out += &format!("pop {}\n", var),
+            }
+        }
+        for (i, param) in on_stack.iter().enumerate() {
+            let source = format!("QWORD PTR [rbp + {}]", 16 + 8 * i);
+            out += &match param.is_float() {
+                true => {
+                    let size = float_size(param);
+                    let source = float_operand(&source, size);
+                    self.write_float_register(param, |dest| {
+                        format!("mov{} {}, {}\n", suffix(size), dest, source)
+                    })
+                }
+                false => self.write_register(param, |dest| format!("mov {}, {}\n", dest, source)),
+            };
+        }
+        // Only the low bytes of narrow parameters are defined by the ABI
+        for param in function.params.iter() {
+            out += &self.wrap(param);
+        }
+        let mut i = 0;
+        while i < function.body.len() {
+            self.line = i;
+            // A call whose result is returned right away jumps to the callee,
+            // which returns to the caller of this function, unless the
+            // result is truncated on the way or the callee may be given the
+            // address of a stack slot of this frame
+            if let tac::Line::Call(name, args, dest) = &function.body[i]
+                && self.tail_calls
+                && dest.is_whole()
+                && self.stack_slots.is_empty()
+                && function.body.get(i + 1) == Some(&tac::Line::Return(*dest))
+                && let Some(jump) = self.compile_tail_call(name, args)
+            {
+                out += &jump;
+                i += 2;
+                continue;
+            }
+            out += &self.compile_line(&function.body[i]);
+            i += 1;
         }
         out
     }
 
     fn compile_program(&mut self, program: &tac::Program) -> String {
         let mut out = String::new();
+        for global in program.globals.iter() {
+            out += &self.compile_global(global);
+        }
+        out += ".text\n";
         for (name, func) in program.functions.iter() {
-            out += &self.compile_function(name, &func.body);
+            out += &self.compile_function(name, func);
         }
-        out
+        if !self.float_constants.is_empty() {
+            out += ".section .rodata\n.balign 8\n";
+            for (i, bits) in self.float_constants.iter().enumerate() {
+                out += &format!(".LF{}:\n.quad {}\n", i, *bits as i64);
+            }
+        }
+        // The stack is not executable, which the linker warns about unless
+        // told
+        out += ".section .note.GNU-stack,\"\",@progbits\n";
+        // GAS starts in AT&T syntax
+        let mut header = String::new();
+        for symbol in self.symbols.iter() {
+            header += &format!(".set .Lsym.{0}, {0}\n", symbol);
+        }
+        header + ".intel_syntax noprefix\n" + &out
     }
 }
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+
+use super::tac::{Address, Line, Program};
+
+// Which functions of a program each function calls. Functions are only named
+// by calls, so these are all the ways control can get from one to another.
+#[derive(Debug)]
+pub struct CallGraph {
+    // The functions defined in the program each one calls, once each, in
+    // the order of the first call
+    pub callees: IndexMap<String, Vec<String>>,
+}
+
+pub fn build(program: &Program) -> CallGraph {
+    let callees = program
+        .functions
+        .iter()
+        .map(|(name, function)| {
+            let mut callees: Vec<String> = vec![];
+            for line in function.body.iter() {
+                if let Line::Call(Address::Global(callee), ..) = line
+                    && program.functions.contains_key(*callee)
+                    && !callees.iter().any(|other| other == callee)
+                {
+                    callees.push(callee.to_string());
+                }
+            }
+            (name.clone(), callees)
+        })
+        .collect();
+    CallGraph { callees }
+}
+
+impl CallGraph {
+    // The functions that can end up calling themselves
+    pub fn recursive(&self) -> HashSet<String> {
+        let mut recursive = HashSet::new();
+        for (name, callees) in self.callees.iter() {
+            let mut seen = HashSet::new();
+            let mut stack: Vec<&String> = callees.iter().collect();
+            while let Some(callee) = stack.pop() {
+                if callee == name {
+                    recursive.insert(name.clone());
+                    break;
+                }
+                if seen.insert(callee) {
+                    stack.extend(self.callees[callee].iter());
+                }
+            }
+        }
+        recursive
+    }
+    // Every function after the functions it calls, apart from calls that
+    // go back round a cycle
+    pub fn leaves_first(&self) -> Vec<&String> {
+        let mut order = vec![];
+        let mut seen = HashSet::new();
+        for name in self.callees.keys() {
+            self.visit(name, &mut seen, &mut order);
+        }
+        order
+    }
+    fn visit<'a>(
+        &'a self,
+        name: &'a String,
+        seen: &mut HashSet<&'a String>,
+        order: &mut Vec<&'a String>,
+    ) {
+        if !seen.insert(name) {
+            return;
+        }
+        for callee in self.callees[name].iter() {
+            self.visit(callee, seen, order);
+        }
+        order.push(name);
+    }
+    // The functions calls can reach from the functions other files can call
+    pub fn reachable(&self, program: &Program) -> HashSet<&String> {
+        let mut reachable = HashSet::new();
+        let mut stack: Vec<&String> = self
+            .callees
+            .keys()
+            .filter(|name| !program.functions[*name].internal)
+            .collect();
+        while let Some(name) = stack.pop() {
+            if reachable.insert(name) {
+                stack.extend(self.callees[name].iter());
+            }
+        }
+        reachable
+    }
+}
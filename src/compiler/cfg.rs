@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use super::tac::{Address, Line};
+
+// Every graph starts in an empty entry block and ends in an empty exit
+// block, which every return leads to
+pub const ENTRY: usize = 0;
+pub const EXIT: usize = 1;
+
+// Picks the value of its argument for the predecessor control came from, in
+// the order of the block's predecessors
+#[derive(Debug, Clone)]
+pub struct Phi {
+    pub dest: Address,
+    pub args: Vec<Address>,
+}
+impl std::fmt::Display for Phi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "phi(")?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        write!(f, "), {}", self.dest)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Block {
+    pub phis: Vec<Phi>,
+    pub lines: Vec<Line>,
+    pub successors: Vec<usize>, // The target of a conditional jump comes first
+    pub predecessors: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct Loop {
+    pub header: usize,
+    pub blocks: Vec<usize>,    // In order, with the header
+    pub parent: Option<usize>, // The index of the loop this one is nested in
+}
+
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+}
+impl Cfg {
+    // Splits a function body into basic blocks. A block starts at a label or
+    // after a jump or return, so only its last line can leave it. The lines
+    // of the blocks, in order, are the body again.
+    pub fn new(body: Vec<Line>) -> Self {
+        let mut blocks = vec![Block::default(), Block::default()];
+        for line in body {
+            let ended = match blocks.last().unwrap().lines.last() {
+                _ if blocks.len() == EXIT + 1 => true,
+                Some(last) => is_terminator(last),
+                None => false,
+            };
+            if ended || matches!(line, Line::Label(_)) {
+                blocks.push(Block::default());
+            }
+            blocks.last_mut().unwrap().lines.push(line);
+        }
+        let labels: HashMap<Address, usize> = blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, block)| match block.lines.first() {
+                Some(Line::Label(label)) => Some((*label, i)),
+                _ => None,
+            })
+            .collect();
+        let mut cfg = Self { blocks };
+        // Falling off the end of the last block leaves the function
+        let next = |i: usize, count: usize| if i + 1 < count { i + 1 } else { EXIT };
+        let count = cfg.blocks.len();
+        cfg.add_edge(ENTRY, next(EXIT, count));
+        for i in EXIT + 1..count {
+            let targets = match cfg.blocks[i].lines.last() {
+                Some(Line::Jump(label)) => vec![labels[label]],
+                Some(Line::JumpIfZero(_, label)) | Some(Line::JumpIfNotZero(_, label)) => {
+                    vec![labels[label], next(i, count)]
+                }
+                Some(Line::Return(_)) => vec![EXIT],
+                _ => vec![next(i, count)],
+            };
+            for target in targets {
+                cfg.add_edge(i, target);
+            }
+        }
+        cfg
+    }
+    pub fn add_edge(&mut self, from: usize, to: usize) {
+        if !self.blocks[from].successors.contains(&to) {
+            self.blocks[from].successors.push(to);
+            self.blocks[to].predecessors.push(from);
+        }
+    }
+    // Also drops the arguments of the phis for the edge
+    pub fn remove_edge(&mut self, from: usize, to: usize) {
+        self.blocks[from]
+            .successors
+            .retain(|successor| *successor != to);
+        if let Some(pred) = self.blocks[to]
+            .predecessors
+            .iter()
+            .position(|pred| *pred == from)
+        {
+            self.blocks[to].predecessors.remove(pred);
+            for phi in self.blocks[to].phis.iter_mut() {
+                phi.args.remove(pred);
+            }
+        }
+    }
+    // Puts an empty block at `index` in the layout, moving the blocks from
+    // there on up one place. Whatever fell through into the block at `index`
+    // falls into the new block, which has no edges yet.
+    pub fn insert_block(&mut self, index: usize) {
+        for block in self.blocks.iter_mut() {
+            for other in block
+                .successors
+                .iter_mut()
+                .chain(block.predecessors.iter_mut())
+            {
+                if *other >= index {
+                    *other += 1;
+                }
+            }
+        }
+        self.blocks.insert(index, Block::default());
+    }
+    // Drops the blocks the entry cannot reach. The exit stays even when
+    // nothing returns.
+    pub fn remove_unreachable(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        for block in self.reverse_postorder() {
+            reachable[block] = true;
+        }
+        reachable[EXIT] = true;
+        for (block, reachable) in reachable.iter().enumerate() {
+            if !reachable {
+                for successor in self.blocks[block].successors.clone() {
+                    self.remove_edge(block, successor);
+                }
+            }
+        }
+        // The new index of every block that stays
+        let mut count = 0;
+        let index: Vec<usize> = reachable
+            .iter()
+            .map(|reachable| {
+                count += *reachable as usize;
+                count - *reachable as usize
+            })
+            .collect();
+        let mut block = 0;
+        self.blocks.retain(|_| {
+            block += 1;
+            reachable[block - 1]
+        });
+        for block in self.blocks.iter_mut() {
+            for successor in block.successors.iter_mut() {
+                *successor = index[*successor];
+            }
+            for pred in block.predecessors.iter_mut() {
+                *pred = index[*pred];
+            }
+        }
+    }
+    // Blocks reachable from the entry, each before its successors except
+    // along back edges
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = vec![];
+        // Each entry is a block and how many of its successors have been seen
+        let mut stack = vec![(ENTRY, 0)];
+        visited[ENTRY] = true;
+        while let Some((block, seen)) = stack.pop() {
+            match self.blocks[block].successors.get(seen) {
+                Some(&next) => {
+                    stack.push((block, seen + 1));
+                    if !visited[next] {
+                        visited[next] = true;
+                        stack.push((next, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+        order.reverse();
+        order
+    }
+    // The immediate dominator of every block, which is None for the entry
+    // and unreachable blocks. Uses the iterative algorithm of Cooper, Harvey
+    // and Kennedy.
+    pub fn dominators(&self) -> Vec<Option<usize>> {
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (i, block) in order.iter().enumerate() {
+            position[*block] = i;
+        }
+        let mut idom = vec![None; self.blocks.len()];
+        idom[ENTRY] = Some(ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().skip(1) {
+                let mut new = None;
+                for &pred in self.blocks[block].predecessors.iter() {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new = Some(match new {
+                        None => pred,
+                        Some(other) => {
+                            // Walk up from both until the paths meet
+                            let (mut a, mut b) = (pred, other);
+                            while a != b {
+                                while position[a] > position[b] {
+                                    a = idom[a].unwrap();
+                                }
+                                while position[b] > position[a] {
+                                    b = idom[b].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if new != idom[block] {
+                    idom[block] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom[ENTRY] = None;
+        idom
+    }
+    // For every block, the blocks where its dominance ends: those it does not
+    // strictly dominate but which have a predecessor it dominates
+    pub fn dominance_frontiers(&self, idom: &[Option<usize>]) -> Vec<Vec<usize>> {
+        let mut frontiers = vec![vec![]; self.blocks.len()];
+        for (block, dominator) in idom.iter().enumerate() {
+            let Some(dominator) = *dominator else {
+                continue;
+            };
+            if self.blocks[block].predecessors.len() < 2 {
+                continue;
+            }
+            for &pred in self.blocks[block].predecessors.iter() {
+                if pred != ENTRY && idom[pred].is_none() {
+                    continue;
+                }
+                let mut runner = pred;
+                while runner != dominator {
+                    if !frontiers[runner].contains(&block) {
+                        frontiers[runner].push(block);
+                    }
+                    runner = idom[runner].unwrap();
+                }
+            }
+        }
+        frontiers
+    }
+    // The natural loops, innermost first. A back edge goes to a block that
+    // dominates its source, and the loop is the blocks that reach the source
+    // without going through the header. Back edges to one header make one
+    // loop.
+    pub fn loops(&self, idom: &[Option<usize>]) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = vec![];
+        for (latch, block) in self.blocks.iter().enumerate() {
+            for &header in block.successors.iter() {
+                // Unreachable blocks are in no loop
+                if idom[latch].is_none() || !dominates(idom, header, latch) {
+                    continue;
+                }
+                let mut blocks = vec![header];
+                let mut stack = vec![latch];
+                while let Some(block) = stack.pop() {
+                    if !blocks.contains(&block) {
+                        blocks.push(block);
+                        stack.extend(self.blocks[block].predecessors.iter());
+                    }
+                }
+                match loops.iter_mut().find(|other| other.header == header) {
+                    Some(other) => {
+                        for block in blocks {
+                            if !other.blocks.contains(&block) {
+                                other.blocks.push(block);
+                            }
+                        }
+                    }
+                    None => loops.push(Loop {
+                        header,
+                        blocks,
+                        parent: None,
+                    }),
+                }
+            }
+        }
+        for other in loops.iter_mut() {
+            other.blocks.sort();
+        }
+        loops.sort_by_key(|other| (other.blocks.len(), other.header));
+        // A loop is nested in the smallest other loop holding its header
+        for i in 0..loops.len() {
+            loops[i].parent =
+                (i + 1..loops.len()).find(|j| loops[*j].blocks.contains(&loops[i].header));
+        }
+        loops
+    }
+    // Graphviz graph of the blocks, with the immediate dominators as dashed
+    // edges. Blocks in loops show how deeply they are nested.
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut out = format!(
+            "digraph \"{}\" {{\nnode [shape=box, fontname=monospace];\n",
+            name
+        );
+        let loops = self.loops(&self.dominators());
+        for (i, block) in self.blocks.iter().enumerate() {
+            let mut depth = 0;
+            let mut innermost = loops.iter().position(|other| other.blocks.contains(&i));
+            while let Some(other) = innermost {
+                depth += 1;
+                innermost = loops[other].parent;
+            }
+            let title = match depth {
+                0 => format!("B{}:", i),
+                _ => format!("B{} (loop depth {}):", i, depth),
+            };
+            let label = match i {
+                ENTRY => "entry".to_string(),
+                EXIT => "exit".to_string(),
+                // \l ends a left aligned line
+                _ => block
+                    .phis
+                    .iter()
+                    .map(|phi| phi.to_string())
+                    .chain(block.lines.iter().map(|line| line.to_string()))
+                    .fold(format!("{}\\l", title), |label, line| {
+                        label + &line.trim_end().replace('"', "\\\"") + "\\l"
+                    }),
+            };
+            out += &format!("b{} [label=\"{}\"];\n", i, label);
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            for successor in block.successors.iter() {
+                out += &format!("b{} -> b{};\n", i, successor);
+            }
+        }
+        for (i, idom) in self.dominators().iter().enumerate() {
+            if let Some(idom) = idom {
+                out += &format!(
+                    "b{} -> b{} [style=dashed, color=gray, constraint=false];\n",
+                    idom, i
+                );
+            }
+        }
+        out + "}\n"
+    }
+}
+
+// The blocks in order with their phis, for reading the graph between passes
+impl std::fmt::Display for Cfg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, block) in self.blocks.iter().enumerate().skip(EXIT + 1) {
+            writeln!(f, "B{}:", i)?;
+            for phi in block.phis.iter() {
+                writeln!(f, "{}", phi)?;
+            }
+            for line in block.lines.iter() {
+                write!(f, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The blocks each block immediately dominates
+pub fn dominator_tree(idom: &[Option<usize>]) -> Vec<Vec<usize>> {
+    let mut children = vec![vec![]; idom.len()];
+    for (block, dominator) in idom.iter().enumerate() {
+        if let Some(dominator) = dominator {
+            children[*dominator].push(block);
+        }
+    }
+    children
+}
+
+// Whether every path from the entry to block `b` goes through block `a`
+pub fn dominates(idom: &[Option<usize>], a: usize, b: usize) -> bool {
+    let mut block = Some(b);
+    while let Some(current) = block {
+        if current == a {
+            return true;
+        }
+        block = idom[current];
+    }
+    false
+}
+
+// Whether the line can jump away, which ends its block
+pub fn is_terminator(line: &Line) -> bool {
+    matches!(
+        line,
+        Line::Jump(_) | Line::JumpIfZero(..) | Line::JumpIfNotZero(..) | Line::Return(_)
+    )
+}
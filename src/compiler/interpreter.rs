@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+
+use super::tac::{self, Address, Int, Line};
+
+// Calls nested deeper than this are taken to be runaway recursion. Frames
+// live on the heap, a few hundred bytes each, so recursion a million deep
+// that only the tail pass would turn into a loop still runs at -O0
+const MAX_DEPTH: usize = 2_000_000;
+// Nothing is placed below this, so null pointers cannot be dereferenced
+const MEMORY_START: u64 = 0x10000;
+// Larger requests fail, like malloc does when it runs out of memory
+const MAX_ALLOCATION: u64 = 1 << 30;
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    text: String,
+}
+impl RuntimeError {
+    fn new<T: ToString>(msg: T) -> Self {
+        Self {
+            text: msg.to_string(),
+        }
+    }
+}
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RuntimeError: {}", self.text)
+    }
+}
+impl std::error::Error for RuntimeError {}
+
+macro_rules! error {
+    ($($args: tt)*) => {
+        Err(RuntimeError::new(format!($($args)*)))
+    }
+}
+
+// What running a program produced
+#[derive(Debug)]
+pub struct Outcome {
+    pub return_value: u64, // Returned by main, extended to 64 bits
+    pub output: String,    // Written by putchar
+}
+
+fn float_size(addr: &Address) -> Option<u64> {
+    match addr {
+        Address::FloatConstant(_, size) | Address::FloatVariable(_, size) => Some(*size),
+        _ => None,
+    }
+}
+
+// The name of a global symbol
+fn symbol(address: &Address) -> Result<&'static str, RuntimeError> {
+    match address {
+        Address::Global(name) => Ok(name),
+        _ => error!("{} is not a global symbol", address),
+    }
+}
+
+// Floating point values are kept as their bit pattern, with single precision
+// values in the low 32 bits
+fn to_float(value: u64, size: u64) -> f64 {
+    match size {
+        4 => f32::from_bits(value as u32) as f64,
+        _ => f64::from_bits(value),
+    }
+}
+
+fn from_float(value: f64, size: u64) -> u64 {
+    match size {
+        4 => (value as f32).to_bits() as u64,
+        _ => value.to_bits(),
+    }
+}
+
+// One call of a function defined in the program
+struct Frame<'a> {
+    name: &'a str,
+    function: &'a tac::Function,
+    variables: HashMap<u64, u64>,
+    slots: HashMap<u64, u64>, // Address of each stack slot
+    stack: usize,             // Size of memory before the stack slots were allocated
+    next: usize,              // Index of the next line to run
+    result: Address,          // Where the caller wants the return value
+}
+impl Frame<'_> {
+    fn get(&self, addr: &Address) -> Result<u64, RuntimeError> {
+        match addr {
+            Address::Constant(value, _) | Address::FloatConstant(value, _) => Ok(*value),
+            Address::Variable(id, _) | Address::FloatVariable(id, _) => {
+                match self.variables.get(id) {
+                    Some(value) => Ok(*value),
+                    None => error!("{} is read before it is written", addr),
+                }
+            }
+            Address::Slot(id) => Ok(self.slots[id]),
+            Address::Global(_) | Address::Label(_) => error!("{} is not a value", addr),
+        }
+    }
+    // Integer values are converted to the variable's type
+    fn set(&mut self, addr: &Address, value: u64) -> Result<(), RuntimeError> {
+        match addr {
+            Address::Variable(id, int) => {
+                self.variables.insert(*id, int.extend(value));
+                Ok(())
+            }
+            Address::FloatVariable(id, _) => {
+                self.variables.insert(*id, value);
+                Ok(())
+            }
+            _ => error!("Cannot write to constant {}", addr),
+        }
+    }
+}
+
+pub struct Interpreter<'a> {
+    program: &'a tac::Program,
+    memory: Vec<u8>,                                  // Starting at MEMORY_START
+    globals: HashMap<String, (u64, u64, bool)>,       // Address, size and signedness
+    labels: HashMap<String, HashMap<Address, usize>>, // Line of each label, by function
+    output: String,
+}
+impl<'a> Interpreter<'a> {
+    pub fn new(program: &'a tac::Program) -> Self {
+        let mut interpreter = Self {
+            program,
+            memory: vec![],
+            globals: HashMap::new(),
+            labels: HashMap::new(),
+            output: String::new(),
+        };
+        for global in program.globals.iter() {
+            // Declarations of globals defined elsewhere have no storage
+            if let Some(value) = global.value {
+                let addr = interpreter.allocate(global.size);
+                interpreter.write(addr, global.size, value).unwrap();
+                interpreter
+                    .globals
+                    .insert(global.name.clone(), (addr, global.size, global.signed));
+            }
+        }
+        for (name, function) in program.functions.iter() {
+            let labels = function
+                .body
+                .iter()
+                .enumerate()
+                .filter_map(|(i, line)| match line {
+                    Line::Label(label) => Some((*label, i)),
+                    _ => None,
+                })
+                .collect();
+            interpreter.labels.insert(name.clone(), labels);
+        }
+        interpreter
+    }
+    // Runs main to completion. Calls are kept on a stack of their own rather
+    // than in recursive calls, so deep recursion in the program is fine.
+    pub fn run(mut self) -> Result<Outcome, RuntimeError> {
+        let mut stack = vec![self.frame("main", &[], Address::Variable(0, Int::LONG))?];
+        loop {
+            let frame = stack.last_mut().unwrap();
+            let line = match frame.function.body.get(frame.next) {
+                Some(line) => line,
+                None => return error!("Function '{}' ends without returning", frame.name),
+            };
+            frame.next += 1;
+            match line {
+                Line::Return(a) => {
+                    let value = frame.get(a)?;
+                    let frame = stack.pop().unwrap();
+                    // The slots can go unless memory was allocated after them
+                    if let Some(end) = frame.slots.values().max()
+                        && self.memory.len() as u64 == end + 8 - MEMORY_START
+                    {
+                        self.memory.truncate(frame.stack);
+                    }
+                    let result = frame.result;
+                    match stack.last_mut() {
+                        Some(caller) => caller.set(&result, value)?,
+                        None => {
+                            return Ok(Outcome {
+                                return_value: value,
+                                output: self.output,
+                            });
+                        }
+                    }
+                }
+                Line::Call(callee, args, dest) => {
+                    let callee = symbol(callee)?;
+                    let args = args
+                        .iter()
+                        .map(|arg| frame.get(arg))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if !self.program.functions.contains_key(callee) {
+                        let value = self.call_library(callee, &args)?;
+                        frame.set(dest, value)?;
+                    } else if stack.len() == MAX_DEPTH {
+                        return error!("Calls nested more than {} deep", MAX_DEPTH);
+                    } else {
+                        stack.push(self.frame(callee, &args, *dest)?);
+                    }
+                }
+                _ => self.execute(frame, line)?,
+            }
+        }
+    }
+    fn allocate(&mut self, size: u64) -> u64 {
+        // Aligned for any type, like malloc
+        let start = (self.memory.len() as u64).next_multiple_of(16);
+        self.memory.resize((start + size.max(1)) as usize, 0);
+        MEMORY_START + start
+    }
+    fn bytes(&mut self, addr: u64, size: u64) -> Result<&mut [u8], RuntimeError> {
+        let start = addr.wrapping_sub(MEMORY_START);
+        match start.checked_add(size) {
+            Some(end) if addr >= MEMORY_START && end <= self.memory.len() as u64 => {
+                Ok(&mut self.memory[start as usize..end as usize])
+            }
+            _ => error!("Invalid access of {} bytes at address {:#x}", size, addr),
+        }
+    }
+    // Reads `size` bytes, zero extended
+    fn read(&mut self, addr: u64, size: u64) -> Result<u64, RuntimeError> {
+        let mut value = [0; 8];
+        value[..size as usize].copy_from_slice(self.bytes(addr, size)?);
+        Ok(u64::from_le_bytes(value))
+    }
+    fn write(&mut self, addr: u64, size: u64, value: u64) -> Result<(), RuntimeError> {
+        self.bytes(addr, size)?
+            .copy_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+    fn global(&self, address: &Address) -> Result<(u64, u64, bool), RuntimeError> {
+        let name = symbol(address)?;
+        match self.globals.get(name) {
+            Some(global) => Ok(*global),
+            None => error!("Global '{}' is not defined", name),
+        }
+    }
+    // The frame for a call of a function defined in the program
+    fn frame(
+        &mut self,
+        name: &str,
+        args: &[u64],
+        result: Address,
+    ) -> Result<Frame<'a>, RuntimeError> {
+        let (name, function) = match self.program.functions.get_key_value(name) {
+            Some(function) => function,
+            None => return error!("Function '{}' is not defined", name),
+        };
+        if function.params.len() != args.len() {
+            return error!(
+                "Function '{}' takes {} arguments, but is called with {}",
+                name,
+                function.params.len(),
+                args.len()
+            );
+        }
+        let mut frame = Frame {
+            name,
+            function,
+            variables: HashMap::new(),
+            slots: HashMap::new(),
+            stack: self.memory.len(),
+            next: 0,
+            result,
+        };
+        for id in function.slots() {
+            frame.slots.insert(id, self.allocate(8));
+        }
+        for (param, arg) in function.params.iter().zip(args) {
+            frame.set(param, *arg)?;
+        }
+        Ok(frame)
+    }
+    // The few C library functions programs are expected to use
+    fn call_library(&mut self, name: &str, args: &[u64]) -> Result<u64, RuntimeError> {
+        match (name, args) {
+            ("malloc", [size]) if *size <= MAX_ALLOCATION => Ok(self.allocate(*size)),
+            ("calloc", [count, size]) if count.saturating_mul(*size) <= MAX_ALLOCATION => {
+                Ok(self.allocate(count * size))
+            }
+            ("malloc", [_]) | ("calloc", [_, _]) => Ok(0),
+            // Memory is never reused, so there is nothing to do
+            ("free", [_]) => Ok(0),
+            ("putchar", [c]) => {
+                self.output.push(*c as u8 as char);
+                Ok(*c as u8 as u64)
+            }
+            _ => error!(
+                "Call to unknown function '{}' with {} arguments",
+                name,
+                args.len()
+            ),
+        }
+    }
+    // Runs a line that does not call or return
+    fn execute(&mut self, frame: &mut Frame, line: &Line) -> Result<(), RuntimeError> {
+        match line {
+            Line::LoadGlobal(symbol, dest) => {
+                let (addr, size, signed) = self.global(symbol)?;
+                let value = self.read(addr, size)?;
+                match dest.is_float() {
+                    true => frame.set(dest, value)?,
+                    false => frame.set(dest, Int { size, signed }.extend(value))?,
+                }
+            }
+            Line::StoreGlobal(source, symbol) => {
+                let (addr, size, _) = self.global(symbol)?;
+                self.write(addr, size, frame.get(source)?)?;
+            }
+            Line::Label(_) => (),
+            Line::Jump(label) => frame.next = self.labels[frame.name][label],
+            Line::JumpIfZero(a, label) => {
+                if frame.get(a)? == 0 {
+                    frame.next = self.labels[frame.name][label];
+                }
+            }
+            Line::JumpIfNotZero(a, label) => {
+                if frame.get(a)? != 0 {
+                    frame.next = self.labels[frame.name][label];
+                }
+            }
+            Line::Return(_) | Line::Call(..) => unreachable!(),
+            Line::Load(pointer, dest, size) => {
+                let value = self.read(frame.get(pointer)?, *size)?;
+                frame.set(dest, value)?;
+            }
+            Line::Store(source, pointer, size) => {
+                self.write(frame.get(pointer)?, *size, frame.get(source)?)?;
+            }
+            _ => {
+                let value = evaluate(line, |addr| frame.get(addr))?;
+                frame.set(line.dest().unwrap(), value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The value a line that only computes from its operands writes, with get
+// reading the operands. Fails where running the line would.
+pub fn evaluate(
+    line: &Line,
+    get: impl Fn(&Address) -> Result<u64, RuntimeError>,
+) -> Result<u64, RuntimeError> {
+    let value = match line {
+        Line::Add(a, b) => arithmetic(&get, a, b, |x, y| x + y, |x, y| Ok(x.wrapping_add(y)))?,
+        Line::Subtract(a, b) => arithmetic(&get, a, b, |x, y| x - y, |x, y| Ok(x.wrapping_sub(y)))?,
+        Line::Multiply(a, b) => arithmetic(&get, a, b, |x, y| x * y, |x, y| Ok(x.wrapping_mul(y)))?,
+        Line::Divide(a, b) => arithmetic(
+            &get,
+            a,
+            b,
+            |x, y| x / y,
+            |x, y| match (x as i64).checked_div(y as i64) {
+                Some(value) => Ok(value as u64),
+                None => error!("Division of {} by {}", x as i64, y as i64),
+            },
+        )?,
+        Line::UnsignedDivide(a, b) => {
+            let (x, y) = (get(a)?, get(b)?);
+            match x.checked_div(y) {
+                Some(value) => value,
+                None => return error!("Division of {} by 0", x),
+            }
+        }
+        Line::Remainder(a, b) => {
+            let (x, y) = (get(a)? as i64, get(b)? as i64);
+            match x.checked_rem(y) {
+                Some(value) => value as u64,
+                None => return error!("Remainder of {} by {}", x, y),
+            }
+        }
+        Line::UnsignedRemainder(a, b) => {
+            let (x, y) = (get(a)?, get(b)?);
+            match x.checked_rem(y) {
+                Some(value) => value,
+                None => return error!("Remainder of {} by 0", x),
+            }
+        }
+        Line::And(a, b) => get(a)? & get(b)?,
+        Line::Or(a, b) => get(a)? | get(b)?,
+        Line::Xor(a, b) => get(a)? ^ get(b)?,
+        // Like the processor, only the low 6 bits of the count are used
+        Line::ShiftLeft(a, b) => get(a)? << (get(b)? & 63),
+        Line::ShiftRight(a, b) => ((get(a)? as i64) >> (get(b)? & 63)) as u64,
+        Line::UnsignedShiftRight(a, b) => get(a)? >> (get(b)? & 63),
+        Line::Negate(a) => get(a)?.wrapping_neg(),
+        Line::Complement(a) => !get(a)?,
+        Line::Not(a) => (get(a)? == 0) as u64,
+        Line::Move(a, _) => get(a)?,
+        Line::SignExtend(a, _, size) => Int {
+            size: *size,
+            signed: true,
+        }
+        .extend(get(a)?),
+        Line::ZeroExtend(a, _, size) => Int {
+            size: *size,
+            signed: false,
+        }
+        .extend(get(a)?),
+        Line::Equal(a, b, _)
+        | Line::NotEqual(a, b, _)
+        | Line::Less(a, b, _)
+        | Line::LessOrEqual(a, b, _)
+        | Line::Greater(a, b, _)
+        | Line::GreaterOrEqual(a, b, _)
+        | Line::UnsignedLess(a, b, _)
+        | Line::UnsignedLessOrEqual(a, b, _)
+        | Line::UnsignedGreater(a, b, _)
+        | Line::UnsignedGreaterOrEqual(a, b, _) => {
+            compare(line, get(a)?, get(b)?, float_size(a)) as u64
+        }
+        // Converting straight to f32 avoids rounding twice
+        Line::IntToFloat(a, b) => {
+            let value = get(a)? as i64;
+            match float_size(b) {
+                Some(4) => (value as f32).to_bits() as u64,
+                _ => (value as f64).to_bits(),
+            }
+        }
+        Line::UnsignedToFloat(a, b) => {
+            let value = get(a)?;
+            match float_size(b) {
+                Some(4) => (value as f32).to_bits() as u64,
+                _ => (value as f64).to_bits(),
+            }
+        }
+        Line::FloatToInt(a, _) => to_float(get(a)?, float_size(a).unwrap_or(8)) as i64 as u64,
+        Line::FloatToUnsigned(a, _) => to_float(get(a)?, float_size(a).unwrap_or(8)) as u64,
+        Line::ConvertFloat(a, b) => {
+            let value = to_float(get(a)?, float_size(a).unwrap_or(8));
+            from_float(value, float_size(b).unwrap_or(8))
+        }
+        _ => {
+            return error!(
+                "{} does more than compute a value",
+                line.to_string().trim_end()
+            );
+        }
+    };
+    Ok(match line.dest().and_then(Address::int) {
+        Some(int) => int.extend(value),
+        None => value,
+    })
+}
+
+// Applies an operation to the values of A and B, as floating point numbers
+// of A's size if A is one
+fn arithmetic(
+    get: &impl Fn(&Address) -> Result<u64, RuntimeError>,
+    a: &Address,
+    b: &Address,
+    float: fn(f64, f64) -> f64,
+    integer: impl Fn(u64, u64) -> Result<u64, RuntimeError>,
+) -> Result<u64, RuntimeError> {
+    let (x, y) = (get(a)?, get(b)?);
+    match float_size(a) {
+        // Single precision operations done in double precision round to
+        // the same result
+        Some(size) => Ok(from_float(
+            float(to_float(x, size), to_float(y, size)),
+            size,
+        )),
+        None => integer(x, y),
+    }
+}
+
+fn compare(line: &Line, x: u64, y: u64, float_size: Option<u64>) -> bool {
+    if let Some(size) = float_size {
+        let (x, y) = (to_float(x, size), to_float(y, size));
+        return match line {
+            Line::Equal(..) => x == y,
+            Line::NotEqual(..) => x != y,
+            Line::Less(..) => x < y,
+            Line::LessOrEqual(..) => x <= y,
+            Line::Greater(..) => x > y,
+            _ => x >= y,
+        };
+    }
+    let (a, b) = (x as i64, y as i64);
+    match line {
+        Line::Equal(..) => x == y,
+        Line::NotEqual(..) => x != y,
+        Line::Less(..) => a < b,
+        Line::LessOrEqual(..) => a <= b,
+        Line::Greater(..) => a > b,
+        Line::GreaterOrEqual(..) => a >= b,
+        Line::UnsignedLess(..) => x < y,
+        Line::UnsignedLessOrEqual(..) => x <= y,
+        Line::UnsignedGreater(..) => x > y,
+        _ => x >= y,
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{Cfg, ENTRY, EXIT};
+use super::tac::{Address, Function};
+
+// The lines from the first to the last where a variable holds a value that
+// may still be read, counting lines by their place in the body
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub address: Address,
+    pub start: usize,
+    pub end: usize,
+}
+impl Interval {
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+}
+
+#[derive(Debug)]
+pub struct Liveness {
+    // The graph of the body, which the sets of blocks are indexed by
+    pub cfg: Cfg,
+    // The variables live on entry to and on exit from each block
+    pub live_in: Vec<HashSet<u64>>,
+    pub live_out: Vec<HashSet<u64>>,
+    // The variables live just before and just after each line of the body
+    pub before: Vec<HashSet<u64>>,
+    pub after: Vec<HashSet<u64>>,
+    // Ordered by start, so the number of an interval is its index
+    pub intervals: Vec<Interval>,
+}
+
+// The sets of each block of the body, one line each
+impl std::fmt::Display for Liveness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let set = |set: &HashSet<u64>| {
+            let mut ids: Vec<u64> = set.iter().copied().collect();
+            ids.sort();
+            let ids: Vec<String> = ids.iter().map(|id| format!("%{}", id)).collect();
+            format!("{{{}}}", ids.join(", "))
+        };
+        for block in EXIT + 1..self.cfg.blocks.len() {
+            writeln!(
+                f,
+                "block {}: in {}, out {}",
+                block,
+                set(&self.live_in[block]),
+                set(&self.live_out[block])
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Works out which variables of a function may still be read at each point.
+// Parameters hold their value from the start of the function.
+pub fn analyze(function: &Function) -> Liveness {
+    let cfg = Cfg::new(function.body.clone());
+    let count = cfg.blocks.len();
+    // The variables each block reads before writing them, and writes
+    let mut uses = vec![HashSet::new(); count];
+    let mut defs = vec![HashSet::new(); count];
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        for line in block.lines.iter() {
+            for id in line.uses().into_iter().filter_map(Address::id) {
+                if !defs[i].contains(&id) {
+                    uses[i].insert(id);
+                }
+            }
+            if let Some(id) = line.dest().and_then(Address::id) {
+                defs[i].insert(id);
+            }
+        }
+    }
+    defs[ENTRY].extend(function.params.iter().filter_map(Address::id));
+    let mut live_in: Vec<HashSet<u64>> = vec![HashSet::new(); count];
+    let mut live_out: Vec<HashSet<u64>> = vec![HashSet::new(); count];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        // Going backwards, most successors are done before their blocks
+        for block in (0..count).rev() {
+            let out: HashSet<u64> = cfg.blocks[block]
+                .successors
+                .iter()
+                .flat_map(|successor| live_in[*successor].iter().copied())
+                .collect();
+            let mut new = uses[block].clone();
+            new.extend(out.difference(&defs[block]));
+            if new != live_in[block] || out != live_out[block] {
+                live_in[block] = new;
+                live_out[block] = out;
+                changed = true;
+            }
+        }
+    }
+    let mut before = vec![];
+    let mut after = vec![];
+    for (block, out) in cfg.blocks.iter().zip(live_out.iter()).skip(EXIT + 1) {
+        let mut live = out.clone();
+        let mut sets = vec![];
+        for line in block.lines.iter().rev() {
+            let out = live.clone();
+            if let Some(id) = line.dest().and_then(Address::id) {
+                live.remove(&id);
+            }
+            live.extend(line.uses().into_iter().filter_map(Address::id));
+            sets.push((live.clone(), out));
+        }
+        for (live_before, live_after) in sets.into_iter().rev() {
+            before.push(live_before);
+            after.push(live_after);
+        }
+    }
+    let intervals = intervals(function, &before, &after);
+    Liveness {
+        cfg,
+        live_in,
+        live_out,
+        before,
+        after,
+        intervals,
+    }
+}
+
+// A variable is given a single interval, which covers any lines in between
+// where it is not live
+fn intervals(
+    function: &Function,
+    before: &[HashSet<u64>],
+    after: &[HashSet<u64>],
+) -> Vec<Interval> {
+    let mut intervals: HashMap<u64, Interval> = HashMap::new();
+    for param in function.params.iter() {
+        cover(&mut intervals, param, 0);
+    }
+    // Every variable is named by some line that uses or writes it, so each
+    // live variable is found with its address
+    for (i, line) in function.body.iter().enumerate() {
+        for address in line.addresses() {
+            cover(&mut intervals, address, i);
+        }
+    }
+    for (i, (before, after)) in before.iter().zip(after.iter()).enumerate() {
+        for id in before.iter().chain(after.iter()) {
+            let address = intervals[id].address;
+            cover(&mut intervals, &address, i);
+        }
+    }
+    let mut intervals: Vec<Interval> = intervals.into_values().collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.end, interval.address));
+    intervals
+}
+
+fn cover(intervals: &mut HashMap<u64, Interval>, address: &Address, line: usize) {
+    if let Some(id) = address.id() {
+        let interval = intervals.entry(id).or_insert(Interval {
+            address: *address,
+            start: line,
+            end: line,
+        });
+        interval.start = interval.start.min(line);
+        interval.end = interval.end.max(line);
+    }
+}
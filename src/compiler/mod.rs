@@ -1,2 +1,8 @@
 pub mod asm;
+pub mod callgraph;
+pub mod cfg;
+pub mod interpreter;
+pub mod liveness;
+pub mod optimize;
+pub mod ssa;
 pub mod tac;
@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+
+use super::super::cfg::{Cfg, ENTRY};
+use super::super::interpreter;
+use super::super::tac::{Address, Line};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Unknown, // Nothing written yet, so it may still turn out to be constant
+    Constant(u64),
+    Varying,
+}
+
+// Finds the variables of a function in SSA form that always hold the same
+// value, and the branches that always go the same way. Those variables are
+// replaced by their value, and the lines computing them become moves of it.
+// Lines that would fail at run time, like a division by 0, are left alone.
+pub fn run(cfg: &mut Cfg) {
+    let mut defined = HashSet::new();
+    for block in cfg.blocks.iter() {
+        defined.extend(block.phis.iter().filter_map(|phi| phi.dest.id()));
+        defined.extend(block.lines.iter().filter_map(|line| line.dest()?.id()));
+    }
+    let mut values = Values {
+        values: HashMap::new(),
+        defined,
+    };
+    let order = cfg.reverse_postorder();
+    let mut executable = vec![false; cfg.blocks.len()];
+    let mut edges = HashSet::new();
+    executable[ENTRY] = true;
+    // Blocks are rerun as a whole, which keeps the value a line updates in
+    // place apart from the one it started with
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter() {
+            if !executable[block] {
+                continue;
+            }
+            for phi in cfg.blocks[block].phis.iter() {
+                let mut value = Value::Unknown;
+                for (pred, arg) in cfg.blocks[block].predecessors.iter().zip(phi.args.iter()) {
+                    if edges.contains(&(*pred, block)) {
+                        value = meet(value, values.get(arg));
+                    }
+                }
+                changed |= values.set(&phi.dest, value);
+            }
+            // Only the values left at the end of the block count as changes
+            let mut before = HashMap::new();
+            for line in cfg.blocks[block].lines.iter() {
+                if let Some(dest) = line.dest() {
+                    let id = dest.id().unwrap();
+                    before
+                        .entry(id)
+                        .or_insert_with(|| values.values.get(&id).copied());
+                    values.set(dest, values.evaluate(line));
+                }
+            }
+            changed |= before
+                .iter()
+                .any(|(id, value)| values.values.get(id).copied() != *value);
+            let successors = &cfg.blocks[block].successors;
+            let taken = match cfg.blocks[block].lines.last() {
+                Some(
+                    line @ (Line::JumpIfZero(condition, _) | Line::JumpIfNotZero(condition, _)),
+                ) => match values.get(condition) {
+                    Value::Unknown => vec![],
+                    Value::Constant(value) => vec![branch(line, value, successors)],
+                    Value::Varying => successors.clone(),
+                },
+                _ => successors.clone(),
+            };
+            for successor in taken {
+                if edges.insert((block, successor)) {
+                    executable[successor] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+    for &block in order.iter() {
+        if !executable[block] {
+            continue;
+        }
+        for phi in cfg.blocks[block].phis.iter_mut() {
+            for arg in phi.args.iter_mut() {
+                values.replace(arg);
+            }
+        }
+        // The values are worked out again on the way, as a variable updated
+        // in place has a different value before the update
+        let mut lines = vec![];
+        for mut line in std::mem::take(&mut cfg.blocks[block].lines) {
+            let dest = line.dest().copied();
+            let value = match dest {
+                Some(dest) => {
+                    let value = values.evaluate(&line);
+                    values.set(&dest, value);
+                    value
+                }
+                None => Value::Varying,
+            };
+            if let (Some(dest), Value::Constant(value)) = (dest, value) {
+                // The copy of the old value to update is not needed either
+                if line.updates_dest()
+                    && matches!(lines.last(), Some(Line::Move(_, copy)) if *copy == dest)
+                {
+                    lines.pop();
+                }
+                lines.push(Line::Move(constant(&dest, value), dest));
+                continue;
+            }
+            for operand in line.operands_mut() {
+                values.replace(operand);
+            }
+            match line {
+                Line::JumpIfZero(Address::Constant(value, _), _)
+                | Line::JumpIfNotZero(Address::Constant(value, _), _) => {
+                    let successors = cfg.blocks[block].successors.clone();
+                    let taken = branch(&line, value, &successors);
+                    for &successor in successors.iter() {
+                        if successor != taken {
+                            cfg.remove_edge(block, successor);
+                        }
+                    }
+                    // Falling through needs no line
+                    if taken == successors[0]
+                        && let Line::JumpIfZero(_, label) | Line::JumpIfNotZero(_, label) = line
+                    {
+                        lines.push(Line::Jump(label));
+                    }
+                }
+                line => lines.push(line),
+            }
+        }
+        cfg.blocks[block].lines = lines;
+    }
+}
+
+struct Values {
+    values: HashMap<u64, Value>,
+    // Variables read without being written anywhere can hold anything
+    defined: HashSet<u64>,
+}
+impl Values {
+    fn get(&self, address: &Address) -> Value {
+        match address {
+            Address::Constant(value, _) | Address::FloatConstant(value, _) => {
+                Value::Constant(*value)
+            }
+            Address::Variable(id, _) | Address::FloatVariable(id, _) => {
+                match self.defined.contains(id) {
+                    true => *self.values.get(id).unwrap_or(&Value::Unknown),
+                    false => Value::Varying,
+                }
+            }
+            // Where the frame is is only known when the function runs
+            Address::Slot(_) => Value::Varying,
+            Address::Global(_) | Address::Label(_) => unreachable!(),
+        }
+    }
+    // Whether the value changed
+    fn set(&mut self, address: &Address, value: Value) -> bool {
+        let id = address.id().unwrap();
+        self.values.insert(id, value) != Some(value)
+    }
+    fn evaluate(&self, line: &Line) -> Value {
+        let inputs: Vec<Value> = line.uses().into_iter().map(|use_| self.get(use_)).collect();
+        if inputs.contains(&Value::Varying) {
+            return Value::Varying;
+        }
+        if inputs.contains(&Value::Unknown) {
+            return Value::Unknown;
+        }
+        // Only the lines that just compute a value can be evaluated, and
+        // they fail where running them would
+        match interpreter::evaluate(line, |address| match self.get(address) {
+            Value::Constant(value) => Ok(value),
+            _ => unreachable!(),
+        }) {
+            Ok(value) => Value::Constant(value),
+            Err(_) => Value::Varying,
+        }
+    }
+    fn replace(&self, address: &mut Address) {
+        if address.id().is_some()
+            && let Value::Constant(value) = self.get(address)
+        {
+            *address = constant(address, value);
+        }
+    }
+}
+
+fn meet(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Unknown, other) | (other, Value::Unknown) => other,
+        (Value::Constant(a), Value::Constant(b)) if a == b => Value::Constant(a),
+        _ => Value::Varying,
+    }
+}
+
+// The successor a conditional jump goes to when its condition has a value.
+// The jump target comes first, and the next block second.
+fn branch(line: &Line, value: u64, successors: &[usize]) -> usize {
+    let jumps = match line {
+        Line::JumpIfZero(..) => value == 0,
+        _ => value != 0,
+    };
+    match jumps {
+        true => successors[0],
+        false => *successors.last().unwrap(),
+    }
+}
+
+// A constant of the same type as an address, which the value has already
+// been converted to
+pub fn constant(address: &Address, value: u64) -> Address {
+    match address {
+        Address::FloatVariable(_, size) | Address::FloatConstant(_, size) => {
+            Address::FloatConstant(value, *size)
+        }
+        _ => Address::Constant(value, address.int().unwrap()),
+    }
+}
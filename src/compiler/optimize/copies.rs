@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use super::super::cfg::Cfg;
+use super::super::tac::{Address, Line};
+
+// Replaces reads of a variable that is only a copy of another value, in a
+// function in SSA form, with that value. The copies are left for the dead
+// code pass. Copies of a value to update in place are kept, as the variable
+// is written twice.
+pub fn run(cfg: &mut Cfg) {
+    let mut copies: HashMap<Address, Address> = HashMap::new();
+    for block in cfg.blocks.iter() {
+        // A phi whose arguments are all one value, apart from itself, is a
+        // copy of it
+        for phi in block.phis.iter() {
+            let mut args = phi.args.iter().filter(|arg| **arg != phi.dest);
+            if let Some(first) = args.next()
+                && args.all(|arg| arg == first)
+                && same_class(first, &phi.dest)
+            {
+                copies.insert(phi.dest, *first);
+            }
+        }
+        for (i, line) in block.lines.iter().enumerate() {
+            if let Line::Move(source, dest) = line
+                && dest.id().is_some()
+                && same_class(source, dest)
+                && !block
+                    .lines
+                    .get(i + 1)
+                    .is_some_and(|next| next.updates_dest() && next.dest() == Some(dest))
+            {
+                copies.insert(*dest, *source);
+            }
+        }
+    }
+    let resolve = |address: &mut Address| {
+        // Copies of phis can go round a loop, so chains are cut off at the
+        // number of copies
+        for _ in 0..copies.len() {
+            match copies.get(address) {
+                Some(source) => *address = *source,
+                None => break,
+            }
+        }
+    };
+    for block in cfg.blocks.iter_mut() {
+        for phi in block.phis.iter_mut() {
+            for arg in phi.args.iter_mut() {
+                resolve(arg);
+            }
+        }
+        for line in block.lines.iter_mut() {
+            for operand in line.operands_mut() {
+                resolve(operand);
+            }
+        }
+    }
+}
+
+// Whether a value can stand in for a variable, which needs the same kind of
+// register, and for integers the same type, as moves between types convert
+pub fn same_class(source: &Address, dest: &Address) -> bool {
+    match (source, dest) {
+        (
+            Address::FloatVariable(_, a) | Address::FloatConstant(_, a),
+            Address::FloatVariable(_, b),
+        ) => a == b,
+        (Address::Variable(_, a) | Address::Constant(_, a), Address::Variable(_, b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::{HashMap, HashSet};
+
+use super::super::cfg::{Cfg, EXIT};
+use super::super::tac::{Address, Line};
+
+// Deletes the blocks that can never run, and the lines and phis of a
+// function in SSA form whose results are never read. Calls and lines without
+// a result act on more than variables, so they always stay.
+pub fn run(cfg: &mut Cfg) {
+    cfg.remove_unreachable();
+    // The variables each variable's value is computed from
+    let mut inputs: HashMap<u64, Vec<u64>> = HashMap::new();
+    let mut worklist = vec![];
+    for block in cfg.blocks.iter() {
+        for phi in block.phis.iter() {
+            let args = phi.args.iter().filter_map(Address::id);
+            inputs
+                .entry(phi.dest.id().unwrap())
+                .or_default()
+                .extend(args);
+        }
+        for line in block.lines.iter() {
+            let uses = line.uses().into_iter().filter_map(Address::id);
+            match removable(line) {
+                Some(id) => inputs.entry(id).or_default().extend(uses),
+                None => worklist.extend(uses),
+            }
+        }
+    }
+    let mut live = HashSet::new();
+    while let Some(id) = worklist.pop() {
+        if live.insert(id)
+            && let Some(ids) = inputs.get(&id)
+        {
+            worklist.extend(ids.iter().copied());
+        }
+    }
+    for block in cfg.blocks.iter_mut() {
+        block
+            .phis
+            .retain(|phi| live.contains(&phi.dest.id().unwrap()));
+        block.lines.retain(|line| match removable(line) {
+            Some(id) => live.contains(&id),
+            None => true,
+        });
+    }
+    // Branches that were decided leave jumps to the block that comes next
+    // anyway
+    for i in EXIT + 1..cfg.blocks.len() - 1 {
+        if let Some(Line::Jump(label)) = cfg.blocks[i].lines.last()
+            && cfg.blocks[i + 1].lines.first() == Some(&Line::Label(*label))
+        {
+            cfg.blocks[i].lines.pop();
+        }
+    }
+    let targets: HashSet<Address> = cfg
+        .blocks
+        .iter()
+        .filter_map(|block| match block.lines.last() {
+            Some(Line::Jump(label))
+            | Some(Line::JumpIfZero(_, label))
+            | Some(Line::JumpIfNotZero(_, label)) => Some(*label),
+            _ => None,
+        })
+        .collect();
+    for block in cfg.blocks.iter_mut() {
+        if let Some(Line::Label(label)) = block.lines.first()
+            && !targets.contains(label)
+        {
+            block.lines.remove(0);
+        }
+    }
+}
+
+// The variable written by a line that does nothing else
+fn removable(line: &Line) -> Option<u64> {
+    match line {
+        Line::Call(..) => None,
+        _ => line.dest()?.id(),
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use super::super::cfg::{Cfg, ENTRY, Loop, Phi, is_terminator};
+use super::super::ssa::renamed;
+use super::super::tac::{Address, Function, Int, Line};
+
+// An induction variable: a phi of a loop header that the loop adds the same
+// constant to on every iteration
+struct Induction {
+    phi: usize,
+    init: Address,
+    step: u64,
+    place: (usize, usize), // The block and line of the copy starting the addition
+    of_next: bool,         // Whether the multiplication is of the variable with the constant added
+}
+
+// Replaces multiplications of the induction variables of the loops of a
+// function in SSA form by constants with variables of their own, which start
+// at the product before the loop and add the step times the constant on every
+// iteration. Values wrap at 64 bits, so the products always match.
+pub fn run(function: &mut Function, cfg: &mut Cfg) {
+    for found in cfg.loops(&cfg.dominators()) {
+        let outside: Vec<usize> = cfg.blocks[found.header]
+            .predecessors
+            .iter()
+            .copied()
+            .filter(|pred| !found.blocks.contains(pred))
+            .collect();
+        // The starting values are worked out at the end of the only block
+        // entering the loop, which has to lead nowhere else
+        let [entry] = outside[..] else {
+            continue;
+        };
+        if entry == ENTRY || cfg.blocks[entry].successors.len() != 1 {
+            continue;
+        }
+        while let Some((induction, (block, line), factor)) = find(cfg, &found, entry) {
+            let header = found.header;
+            let dest = cfg.blocks[header].phis[induction.phi].dest;
+            let base = renamed(&dest, function.var_label());
+            let scaled = renamed(&dest, function.var_label());
+            let next = renamed(&dest, function.var_label());
+            let lines = &mut cfg.blocks[entry].lines;
+            let end = match lines.last() {
+                Some(last) if is_terminator(last) => lines.len() - 1,
+                _ => lines.len(),
+            };
+            // Constant propagation has already run, so a constant start is
+            // multiplied here
+            let start = match induction.init {
+                Address::Constant(value, _) => {
+                    let int = base.int().unwrap();
+                    let product = int.extend(value.wrapping_mul(factor));
+                    vec![Line::Move(Address::Constant(product, int), base)]
+                }
+                init => vec![
+                    Line::Move(init, base),
+                    Line::Multiply(base, Address::Constant(factor, Int::LONG)),
+                ],
+            };
+            lines.splice(end..end, start);
+            let args = cfg.blocks[header]
+                .predecessors
+                .iter()
+                .map(|pred| if *pred == entry { base } else { next })
+                .collect();
+            cfg.blocks[header].phis.push(Phi { dest: scaled, args });
+            // The product of the variable with the constant added is the new
+            // variable with the step added, so the multiplication becomes a
+            // copy of one or the other
+            let Line::Move(_, product) = cfg.blocks[block].lines[line] else {
+                unreachable!()
+            };
+            let value = match induction.of_next {
+                true => next,
+                false => scaled,
+            };
+            cfg.blocks[block]
+                .lines
+                .splice(line..line + 2, [Line::Move(value, product)]);
+            // Removing a line before the addition moves it back
+            let (at, mut index) = induction.place;
+            if at == block && line < index {
+                index -= 1;
+            }
+            cfg.blocks[at].lines.splice(
+                index + 2..index + 2,
+                [
+                    Line::Move(scaled, next),
+                    Line::Add(
+                        next,
+                        Address::Constant(induction.step.wrapping_mul(factor), Int::LONG),
+                    ),
+                ],
+            );
+        }
+    }
+}
+
+// An induction variable of the loop, and a multiplication in the loop of it
+// or of it with the step added by a constant, with the constant
+fn find(cfg: &Cfg, found: &Loop, entry: usize) -> Option<(Induction, (usize, usize), u64)> {
+    let header = &cfg.blocks[found.header];
+    let copies = copies(cfg, found);
+    let original = |address: &Address| {
+        let mut address = *address;
+        while let Some(source) = copies.get(&address) {
+            address = *source;
+        }
+        address
+    };
+    for (phi, candidate) in header.phis.iter().enumerate() {
+        if candidate.dest.is_float() {
+            continue;
+        }
+        let mut init = None;
+        let mut inside = vec![];
+        for (pred, arg) in header.predecessors.iter().zip(candidate.args.iter()) {
+            match *pred == entry {
+                true => init = Some(*arg),
+                false => inside.push(*arg),
+            }
+        }
+        // Every iteration has to go on with the same variable
+        let (Some(init), Some(&next)) = (init, inside.first()) else {
+            continue;
+        };
+        if inside.iter().any(|arg| *arg != next) {
+            continue;
+        }
+        let Some((place, step)) = addition(cfg, found, &candidate.dest, &next, original) else {
+            continue;
+        };
+        for &block in found.blocks.iter() {
+            let lines = &cfg.blocks[block].lines;
+            for (line, pair) in lines.windows(2).enumerate() {
+                let [Line::Move(source, product), multiply] = pair else {
+                    continue;
+                };
+                // The product wraps like the variable only if they have one type
+                let source = original(source);
+                if (source != candidate.dest && source != next)
+                    || multiply.dest() != Some(product)
+                    || product.int() != candidate.dest.int()
+                {
+                    continue;
+                }
+                let factor = match multiply {
+                    Line::Multiply(_, Address::Constant(factor, _)) => *factor,
+                    Line::ShiftLeft(_, Address::Constant(shift, _)) => 1 << (shift & 63),
+                    _ => continue,
+                };
+                let induction = Induction {
+                    phi,
+                    init,
+                    step,
+                    place,
+                    of_next: source == next,
+                };
+                return Some((induction, (block, line), factor));
+            }
+        }
+    }
+    None
+}
+
+// The variables of the loop that are only copies of another variable of the
+// same type, which the copy propagation pass has not replaced yet
+fn copies(cfg: &Cfg, found: &Loop) -> HashMap<Address, Address> {
+    let mut copies = HashMap::new();
+    for &block in found.blocks.iter() {
+        let lines = &cfg.blocks[block].lines;
+        for (i, line) in lines.iter().enumerate() {
+            if let Line::Move(source, dest) = line
+                && source.id().is_some()
+                && renamed(source, 0) == renamed(dest, 0)
+                && !lines
+                    .get(i + 1)
+                    .is_some_and(|next| next.updates_dest() && next.dest() == Some(dest))
+            {
+                copies.insert(*dest, *source);
+            }
+        }
+    }
+    copies
+}
+
+// Where the loop adds a constant to a variable to give another, and the
+// constant
+fn addition(
+    cfg: &Cfg,
+    found: &Loop,
+    variable: &Address,
+    next: &Address,
+    original: impl Fn(&Address) -> Address,
+) -> Option<((usize, usize), u64)> {
+    for &block in found.blocks.iter() {
+        for (line, pair) in cfg.blocks[block].lines.windows(2).enumerate() {
+            match pair {
+                [
+                    Line::Move(source, dest),
+                    Line::Add(update, Address::Constant(step, _)),
+                ] if original(source) == *variable && dest == next && update == next => {
+                    return Some(((block, line), *step));
+                }
+                [
+                    Line::Move(source, dest),
+                    Line::Subtract(update, Address::Constant(step, _)),
+                ] if original(source) == *variable && dest == next && update == next => {
+                    return Some(((block, line), step.wrapping_neg()));
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
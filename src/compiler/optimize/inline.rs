@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use super::super::callgraph;
+use super::super::ssa::renamed;
+use super::super::tac::{Address, Function, Line, Program};
+
+// Largest body, in lines other than labels, that is copied into its callers
+const MAX_COST: usize = 16;
+// Lines a function may grow by through inlining
+const MAX_GROWTH: usize = 256;
+
+// Replaces calls to small functions with a copy of their body, which saves
+// the call and lets the other passes work on the body with the arguments.
+// Functions are done from the leaves of the call graph up, so a body is
+// copied, and its size judged, with its own calls already inlined.
+// Functions that can call themselves are never inlined, so inlining stops.
+pub fn run(program: &mut Program) {
+    let graph = callgraph::build(program);
+    let recursive = graph.recursive();
+    let order: Vec<String> = graph.leaves_first().into_iter().cloned().collect();
+    // Bodies of the functions done so far that are worth copying, with
+    // their parameters
+    let mut callees: HashMap<String, (Vec<Address>, Vec<Line>)> = HashMap::new();
+    for name in order {
+        let function = &mut program.functions[&name];
+        let limit = function.body.len() + MAX_GROWTH;
+        let mut i = 0;
+        while i < function.body.len() {
+            if let Line::Call(Address::Global(callee), args, dest) = function.body[i].clone()
+                && let Some((params, body)) = callees.get(callee)
+                && function.body.len() + body.len() <= limit
+                && let Some(lines) = inline(function, params, body, &args, &dest)
+            {
+                // The calls of the copied body were inlined before it was
+                // copied, so they are not looked at again
+                let length = lines.len();
+                function.body.splice(i..i + 1, lines);
+                i += length;
+            } else {
+                i += 1;
+            }
+        }
+        if !recursive.contains(&name) && cost(function) <= MAX_COST {
+            let callee = (function.params.clone(), function.body.clone());
+            callees.insert(name, callee);
+        }
+    }
+}
+
+fn cost(function: &Function) -> usize {
+    function
+        .body
+        .iter()
+        .filter(|line| !matches!(line, Line::Label(_)))
+        .count()
+}
+
+// The body of the callee with its variables and labels renamed into the
+// caller. The parameters are copied from the arguments first, and each
+// return copies its value to the result and jumps past the end. None when
+// the values do not fit the variables they are copied to.
+fn inline(
+    caller: &mut Function,
+    params: &[Address],
+    body: &[Line],
+    args: &[Address],
+    dest: &Address,
+) -> Option<Vec<Line>> {
+    if params.len() != args.len() || !params.iter().zip(args).all(|(p, a)| fits(a, p)) {
+        return None;
+    }
+    // Falling off the end of a function returns the integer 0, which is
+    // the only value that changes kind
+    if !body.iter().all(|line| match line {
+        Line::Return(value) => fits(value, dest) || matches!(value, Address::Constant(0, _)),
+        _ => true,
+    }) {
+        return None;
+    }
+    let mut variables = HashMap::new();
+    let mut labels = HashMap::new();
+    // Stack slots of the callee become new slots of the caller
+    let first = caller.slots().into_iter().max().map_or(0, |id| id + 1);
+    let mut slots = HashMap::new();
+    let end = caller.jump_label();
+    let mut lines = vec![];
+    for (param, arg) in params.iter().zip(args) {
+        let param = rename(caller, &mut variables, param);
+        lines.push(Line::Move(*arg, param));
+    }
+    for line in body {
+        let mut line = line.clone();
+        for address in line.operands_mut() {
+            *address = match address {
+                Address::Slot(id) => {
+                    let count = slots.len() as u64;
+                    Address::Slot(*slots.entry(*id).or_insert(first + count))
+                }
+                _ => rename(caller, &mut variables, address),
+            };
+        }
+        if let Some(address) = line.dest_mut() {
+            *address = rename(caller, &mut variables, address);
+        }
+        match &mut line {
+            Line::Label(label)
+            | Line::Jump(label)
+            | Line::JumpIfZero(_, label)
+            | Line::JumpIfNotZero(_, label) => {
+                *label = *labels.entry(*label).or_insert_with(|| caller.jump_label());
+            }
+            _ => {}
+        }
+        match line {
+            Line::Return(value) => {
+                let value = match fits(&value, dest) {
+                    true => value,
+                    false => zero(dest),
+                };
+                lines.push(Line::Move(value, *dest));
+                lines.push(Line::Jump(end));
+            }
+            line => lines.push(line),
+        }
+    }
+    lines.push(Line::Label(end));
+    Some(lines)
+}
+
+// Whether a value can be copied to a variable, which needs the same kind of
+// register
+pub fn fits(value: &Address, variable: &Address) -> bool {
+    match (value, variable) {
+        (
+            Address::FloatVariable(_, a) | Address::FloatConstant(_, a),
+            Address::FloatVariable(_, b),
+        ) => a == b,
+        (Address::Variable(..) | Address::Constant(..), Address::Variable(..)) => true,
+        _ => false,
+    }
+}
+
+fn zero(variable: &Address) -> Address {
+    match variable {
+        Address::FloatVariable(_, size) => Address::FloatConstant(0, *size),
+        _ => Address::Constant(0, variable.int().unwrap()),
+    }
+}
+
+// The caller's name for a variable of the callee
+fn rename(caller: &mut Function, variables: &mut HashMap<u64, u64>, address: &Address) -> Address {
+    let Some(id) = address.id() else {
+        return *address;
+    };
+    let id = *variables.entry(id).or_insert_with(|| caller.var_label());
+    renamed(address, id)
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use super::super::callgraph;
+use super::super::interpreter::evaluate;
+use super::super::tac::{Address, Function, Line, Program};
+use super::constants;
+use super::inline::fits;
+
+// Finds the parameters of static functions that every call passes the same
+// constant, and copies the constant to them at the start of the body for
+// the constant propagation pass. Callers go first, so a parameter passed on
+// unchanged from a caller where it is constant counts as that constant.
+// Other files may call functions that are not static, with any arguments.
+pub fn run(program: &mut Program) {
+    let graph = callgraph::build(program);
+    let order: Vec<String> = graph.leaves_first().into_iter().rev().cloned().collect();
+    // The constant each parameter always holds, if any
+    let mut known: HashMap<String, Vec<Option<Address>>> = HashMap::new();
+    for name in order {
+        let function = &program.functions[&name];
+        if !function.internal {
+            continue;
+        }
+        let mut values: Option<Vec<Option<Address>>> = None;
+        for (caller, body) in program.functions.iter() {
+            for line in body.body.iter() {
+                let Line::Call(Address::Global(callee), args, _) = line else {
+                    continue;
+                };
+                if *callee != name {
+                    continue;
+                }
+                let passed: Vec<Option<Address>> = match args.len() == function.params.len() {
+                    true => args
+                        .iter()
+                        .zip(function.params.iter())
+                        .map(|(arg, param)| {
+                            let value = constant(arg, body, known.get(caller))?;
+                            fits(&value, param).then(|| converted(&value, param))?
+                        })
+                        .collect(),
+                    false => vec![None; function.params.len()],
+                };
+                values = Some(match values {
+                    None => passed,
+                    Some(values) => values
+                        .into_iter()
+                        .zip(passed)
+                        .map(|(a, b)| a.filter(|_| a == b))
+                        .collect(),
+                });
+            }
+        }
+        // A function nothing calls is left for the unused function pass
+        if let Some(values) = values {
+            known.insert(name, values);
+        }
+    }
+    for (name, values) in known {
+        let function = &mut program.functions[&name];
+        let moves: Vec<Line> = values
+            .into_iter()
+            .zip(function.params.iter())
+            .filter_map(|(value, param)| Some(Line::Move(value?, *param)))
+            .collect();
+        function.body.splice(0..0, moves);
+    }
+}
+
+// The constant an argument always is. A parameter of the caller that is
+// always a constant counts if the caller never changes it.
+fn constant(
+    arg: &Address,
+    caller: &Function,
+    known: Option<&Vec<Option<Address>>>,
+) -> Option<Address> {
+    if let Address::Constant(..) | Address::FloatConstant(..) = arg {
+        return Some(*arg);
+    }
+    let index = caller.params.iter().position(|param| param == arg)?;
+    let value = known?[index]?;
+    let unchanged = caller.body.iter().all(|line| line.dest() != Some(arg));
+    unchanged.then_some(value)
+}
+
+// A constant as the value a parameter holds once it is copied there, which
+// for integers keeps the low bytes for the parameter's type
+fn converted(value: &Address, param: &Address) -> Option<Address> {
+    let (Address::Constant(bits, _) | Address::FloatConstant(bits, _)) = value else {
+        return None;
+    };
+    let bits = evaluate(&Line::Move(*value, *param), |_| Ok(*bits)).ok()?;
+    Some(constants::constant(param, bits))
+}
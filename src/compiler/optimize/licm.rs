@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+
+use super::super::cfg::{Cfg, EXIT, Phi};
+use super::super::ssa::renamed;
+use super::super::tac::{Address, Function, Line};
+
+// Moves the computations of a function in SSA form that give the same value
+// on every iteration of a loop into its preheader, a new block the loop is
+// entered through. Inner loops go first, so what leaves them can then leave
+// the loops around them.
+pub fn run(function: &mut Function, cfg: &mut Cfg) {
+    // Headers are known by their labels, as adding blocks moves them
+    let mut done = HashSet::new();
+    loop {
+        let loops = cfg.loops(&cfg.dominators());
+        let Some(found) = loops
+            .iter()
+            .find(|found| !done.contains(&label(cfg, found.header)))
+        else {
+            break;
+        };
+        let target = label(cfg, found.header);
+        done.insert(target);
+        let preheader = insert_preheader(function, cfg, found.header, &found.blocks);
+        let header = preheader + 1;
+        // The loop is found again, as the blocks have moved
+        let loops = cfg.loops(&cfg.dominators());
+        let blocks = &loops
+            .iter()
+            .find(|other| other.header == header)
+            .unwrap()
+            .blocks;
+        hoist(cfg, preheader, blocks);
+    }
+}
+
+// A header always has a label, as the back edge jumps to it
+fn label(cfg: &Cfg, block: usize) -> Address {
+    match cfg.blocks[block].lines.first() {
+        Some(Line::Label(label)) => *label,
+        _ => unreachable!(),
+    }
+}
+
+// Puts a block right before the header that the edges from outside the loop
+// go to instead, and which falls through into the header. Where those edges
+// give a phi different values, the preheader gets a phi to pick one.
+// Returns the index of the preheader.
+fn insert_preheader(
+    function: &mut Function,
+    cfg: &mut Cfg,
+    mut header: usize,
+    blocks: &[usize],
+) -> usize {
+    let target = label(cfg, header);
+    let mut outside: Vec<usize> = cfg.blocks[header]
+        .predecessors
+        .iter()
+        .copied()
+        .filter(|pred| !blocks.contains(pred))
+        .collect();
+    let shift = |blocks: &mut Vec<usize>, index: usize| {
+        for block in blocks.iter_mut() {
+            if *block >= index {
+                *block += 1;
+            }
+        }
+    };
+    // A block of the loop falling through into the header would fall into
+    // the preheader, so it jumps to the header from a block of its own
+    let before = header - 1;
+    if before > EXIT
+        && blocks.contains(&before)
+        && !matches!(
+            cfg.blocks[before].lines.last(),
+            Some(Line::Jump(_) | Line::Return(_))
+        )
+    {
+        // Going to the header either way needs no condition
+        if let Some(Line::JumpIfZero(_, label) | Line::JumpIfNotZero(_, label)) =
+            cfg.blocks[before].lines.last()
+            && *label == target
+        {
+            cfg.blocks[before].lines.pop();
+        }
+        cfg.insert_block(header);
+        shift(&mut outside, header);
+        let jump = header;
+        header += 1;
+        for successor in cfg.blocks[before].successors.iter_mut() {
+            if *successor == header {
+                *successor = jump;
+            }
+        }
+        for pred in cfg.blocks[header].predecessors.iter_mut() {
+            if *pred == before {
+                *pred = jump;
+            }
+        }
+        cfg.blocks[jump].lines.push(Line::Jump(target));
+        cfg.blocks[jump].predecessors.push(before);
+        cfg.blocks[jump].successors.push(header);
+    }
+    cfg.insert_block(header);
+    shift(&mut outside, header);
+    let preheader = header;
+    header += 1;
+    let name = function.jump_label();
+    cfg.blocks[preheader].lines.push(Line::Label(name));
+    // The values each phi of the header gets from outside the loop
+    let mut values = vec![vec![]; cfg.blocks[header].phis.len()];
+    for pred in outside {
+        let position = cfg.blocks[header]
+            .predecessors
+            .iter()
+            .position(|other| *other == pred)
+            .unwrap();
+        cfg.blocks[header].predecessors.remove(position);
+        for (phi, values) in cfg.blocks[header].phis.iter_mut().zip(values.iter_mut()) {
+            values.push(phi.args.remove(position));
+        }
+        for successor in cfg.blocks[pred].successors.iter_mut() {
+            if *successor == header {
+                *successor = preheader;
+            }
+        }
+        if let Some(Line::Jump(label) | Line::JumpIfZero(_, label) | Line::JumpIfNotZero(_, label)) =
+            cfg.blocks[pred].lines.last_mut()
+            && *label == target
+        {
+            *label = name;
+        }
+        cfg.blocks[preheader].predecessors.push(pred);
+    }
+    for (i, values) in values.into_iter().enumerate() {
+        let value = match values.iter().all(|value| *value == values[0]) {
+            true => values[0],
+            false => {
+                let dest = renamed(&cfg.blocks[header].phis[i].dest, function.var_label());
+                cfg.blocks[preheader].phis.push(Phi { dest, args: values });
+                dest
+            }
+        };
+        cfg.blocks[header].phis[i].args.push(value);
+    }
+    cfg.blocks[header].predecessors.push(preheader);
+    cfg.blocks[preheader].successors.push(header);
+    preheader
+}
+
+// Moves lines whose operands are all written outside the loop to the end of
+// the preheader, until no more can go. A copy of a value followed by the line
+// updating it moves as one.
+fn hoist(cfg: &mut Cfg, preheader: usize, blocks: &[usize]) {
+    let mut defined = HashSet::new();
+    for &block in blocks {
+        let block = &cfg.blocks[block];
+        defined.extend(block.phis.iter().filter_map(|phi| phi.dest.id()));
+        defined.extend(block.lines.iter().filter_map(|line| line.dest()?.id()));
+    }
+    // Blocks before their successors, so lines come out in an order where
+    // each follows the lines it reads
+    let order: Vec<usize> = cfg
+        .reverse_postorder()
+        .into_iter()
+        .filter(|block| blocks.contains(block))
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter() {
+            let lines = &mut cfg.blocks[block].lines;
+            let mut hoisted = vec![];
+            let mut i = 0;
+            while i < lines.len() {
+                let length = match (&lines[i], lines.get(i + 1)) {
+                    (Line::Move(_, dest), Some(update))
+                        if update.updates_dest() && update.dest() == Some(dest) =>
+                    {
+                        2
+                    }
+                    _ => 1,
+                };
+                let last = &lines[i + length - 1];
+                // An update on its own reads a value written in the loop
+                let invariant = can_hoist(last)
+                    && (length == 2 || !last.updates_dest())
+                    && lines[i..i + length]
+                        .iter()
+                        .flat_map(|line| line.operands())
+                        .all(|operand| !operand.id().is_some_and(|id| defined.contains(&id)));
+                if !invariant {
+                    i += length;
+                    continue;
+                }
+                defined.remove(&last.dest().unwrap().id().unwrap());
+                hoisted.extend(lines.drain(i..i + length));
+                changed = true;
+            }
+            cfg.blocks[preheader].lines.append(&mut hoisted);
+        }
+    }
+}
+
+// Lines that only compute a value and cannot fail, so running them when the
+// loop would not have does no harm
+fn can_hoist(line: &Line) -> bool {
+    match line {
+        Line::Divide(a, b)
+        | Line::UnsignedDivide(a, b)
+        | Line::Remainder(a, b)
+        | Line::UnsignedRemainder(a, b) => {
+            // Dividing by 0, or the most negative value by -1, traps
+            a.is_float()
+                || matches!(b, Address::Constant(value, _) if *value != 0 && *value != u64::MAX)
+        }
+        Line::Add(..)
+        | Line::Subtract(..)
+        | Line::Multiply(..)
+        | Line::And(..)
+        | Line::Or(..)
+        | Line::Xor(..)
+        | Line::ShiftLeft(..)
+        | Line::ShiftRight(..)
+        | Line::UnsignedShiftRight(..)
+        | Line::Negate(..)
+        | Line::Complement(..)
+        | Line::Not(..)
+        | Line::Move(..)
+        | Line::SignExtend(..)
+        | Line::ZeroExtend(..)
+        | Line::Equal(..)
+        | Line::NotEqual(..)
+        | Line::Less(..)
+        | Line::LessOrEqual(..)
+        | Line::Greater(..)
+        | Line::GreaterOrEqual(..)
+        | Line::UnsignedLess(..)
+        | Line::UnsignedLessOrEqual(..)
+        | Line::UnsignedGreater(..)
+        | Line::UnsignedGreaterOrEqual(..)
+        | Line::IntToFloat(..)
+        | Line::UnsignedToFloat(..)
+        | Line::FloatToInt(..)
+        | Line::FloatToUnsigned(..)
+        | Line::ConvertFloat(..) => line.dest().is_some_and(|dest| dest.id().is_some()),
+        _ => false,
+    }
+}
@@ -0,0 +1,231 @@
+pub mod constants;
+pub mod copies;
+pub mod dead;
+pub mod induction;
+pub mod inline;
+pub mod ipcp;
+pub mod licm;
+pub mod numbering;
+pub mod simplify;
+pub mod tail;
+pub mod unused;
+
+use std::collections::HashSet;
+
+use super::cfg::Cfg;
+use super::ssa;
+use super::tac::{Function, Program};
+
+// Function passes work on the graph of one function in SSA form, with the
+// function for new names, and program passes on the three address code of
+// the whole program
+#[derive(Clone, Copy)]
+enum Run {
+    Function(fn(&mut Function, &mut Cfg)),
+    Program(fn(&mut Program)),
+}
+
+// A named step of the optimizer
+pub struct Pass {
+    pub name: &'static str,
+    run: Run,
+    level: u8, // The lowest -O level that runs it
+}
+
+// Every pass, in the order they run
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "tail",
+        run: Run::Program(tail::run),
+        level: 1,
+    },
+    Pass {
+        name: "ipcp",
+        run: Run::Program(ipcp::run),
+        level: 2,
+    },
+    Pass {
+        name: "inline",
+        run: Run::Program(inline::run),
+        level: 2,
+    },
+    Pass {
+        name: "constants",
+        run: Run::Function(|_, cfg| constants::run(cfg)),
+        level: 1,
+    },
+    Pass {
+        name: "simplify",
+        run: Run::Function(|_, cfg| simplify::run(cfg)),
+        level: 1,
+    },
+    Pass {
+        name: "numbering",
+        run: Run::Function(|_, cfg| numbering::run(cfg)),
+        level: 2,
+    },
+    Pass {
+        name: "licm",
+        run: Run::Function(licm::run),
+        level: 2,
+    },
+    Pass {
+        name: "induction",
+        run: Run::Function(induction::run),
+        level: 2,
+    },
+    Pass {
+        name: "copies",
+        run: Run::Function(|_, cfg| copies::run(cfg)),
+        level: 1,
+    },
+    Pass {
+        name: "dead",
+        run: Run::Function(|_, cfg| dead::run(cfg)),
+        level: 1,
+    },
+    Pass {
+        name: "unused",
+        run: Run::Program(unused::run),
+        level: 1,
+    },
+];
+
+#[derive(Debug)]
+pub struct UnknownPass {
+    pub name: String,
+}
+impl std::fmt::Display for UnknownPass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown pass '{}'", self.name)
+    }
+}
+impl std::error::Error for UnknownPass {}
+
+// Runs the passes an optimization level picks, with single passes switched
+// on or off on top, and prints the functions after the chosen passes
+pub struct PassManager {
+    enabled: HashSet<&'static str>,
+    print_after: HashSet<&'static str>,
+    dot: bool,
+}
+impl PassManager {
+    pub fn new(level: u8) -> Self {
+        Self {
+            enabled: PASSES
+                .iter()
+                .filter(|pass| pass.level <= level)
+                .map(|pass| pass.name)
+                .collect(),
+            print_after: HashSet::new(),
+            dot: false,
+        }
+    }
+    fn find(name: &str) -> Result<&'static str, UnknownPass> {
+        match PASSES.iter().find(|pass| pass.name == name) {
+            Some(pass) => Ok(pass.name),
+            None => Err(UnknownPass {
+                name: name.to_string(),
+            }),
+        }
+    }
+    pub fn enable(&mut self, name: &str) -> Result<(), UnknownPass> {
+        self.enabled.insert(Self::find(name)?);
+        Ok(())
+    }
+    pub fn disable(&mut self, name: &str) -> Result<(), UnknownPass> {
+        self.enabled.remove(Self::find(name)?);
+        Ok(())
+    }
+    pub fn enabled(&self, name: &str) -> bool {
+        self.enabled.contains(name)
+    }
+    pub fn print_after(&mut self, name: &str) -> Result<(), UnknownPass> {
+        self.print_after.insert(Self::find(name)?);
+        Ok(())
+    }
+    pub fn print_after_all(&mut self) {
+        self.print_after.extend(PASSES.iter().map(|pass| pass.name));
+    }
+    // Writes the graph of each function after the passes to out/<name>.dot
+    pub fn write_dot(&mut self) {
+        self.dot = true;
+    }
+    pub fn run(&self, program: &mut Program) {
+        verify(program, "parsing");
+        // Each run of function passes goes through SSA form once
+        let mut groups: Vec<Vec<&Pass>> = vec![];
+        let mut function = false;
+        for pass in PASSES.iter() {
+            if !self.enabled.contains(pass.name) {
+                continue;
+            }
+            match pass.run {
+                Run::Function(_) if function => groups.last_mut().unwrap().push(pass),
+                _ => groups.push(vec![pass]),
+            }
+            function = matches!(pass.run, Run::Function(_));
+        }
+        // The graph written out is the one after the last function pass
+        let last = groups
+            .iter()
+            .rposition(|group| matches!(group[0].run, Run::Function(_)));
+        for (i, group) in groups.iter().enumerate() {
+            match group[0].run {
+                Run::Program(run) => {
+                    run(program);
+                    verify(program, group[0].name);
+                    if self.print_after.contains(group[0].name) {
+                        println!("After {}:\n{}", group[0].name, program);
+                    }
+                }
+                Run::Function(_) => self.run_functions(program, group, Some(i) == last),
+            }
+        }
+        // Without function passes the body is left exactly as it was, unless
+        // the graph is wanted
+        if last.is_none() && self.dot {
+            self.run_functions(program, &[], true);
+        }
+    }
+    fn run_functions(&self, program: &mut Program, passes: &[&Pass], last: bool) {
+        for (name, function) in program.functions.iter_mut() {
+            let mut cfg = ssa::construct(function);
+            for pass in passes.iter() {
+                let Run::Function(run) = pass.run else {
+                    unreachable!()
+                };
+                run(function, &mut cfg);
+                // The graph is checked as the code it turns back into
+                if cfg!(debug_assertions) {
+                    let mut copy = function.clone();
+                    ssa::destruct(&mut copy, cfg.clone());
+                    check(&copy, name, pass.name);
+                }
+                if self.print_after.contains(pass.name) {
+                    println!("After {}, function {}:\n{}", pass.name, name, cfg);
+                }
+            }
+            if self.dot && last {
+                std::fs::write(format!("out/{}.dot", name), cfg.to_dot(name)).unwrap();
+            }
+            ssa::destruct(function, cfg);
+        }
+    }
+}
+
+// In debug builds, stops at the first pass that leaves a function malformed
+fn verify(program: &Program, pass: &str) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    for (name, function) in program.functions.iter() {
+        check(function, name, pass);
+    }
+}
+
+fn check(function: &Function, name: &str, pass: &str) {
+    if let Err(error) = function.verify() {
+        panic!("After {}, function {}: {}", pass, name, error);
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::mem::Discriminant;
+
+use super::super::cfg::{Cfg, ENTRY, dominator_tree};
+use super::super::ssa::renamed;
+use super::super::tac::{Address, Line};
+use super::copies::same_class;
+
+// The kind of line, its operands with copies seen through, the size of an
+// extension, and the type of the result, as a variable with id 0
+type Expression = (Discriminant<Line>, Vec<Address>, u64, Address);
+
+// Replaces computations of a function in SSA form that a dominating line
+// has already done with a copy of its result. A line updating a variable in
+// place computes from the value copied into it on the line before.
+pub fn run(cfg: &mut Cfg) {
+    let children = dominator_tree(&cfg.dominators());
+    let mut numbering = Numbering {
+        copies: HashMap::new(),
+        results: HashMap::new(),
+    };
+    // Blocks to visit, and the expressions to forget once they are done
+    let mut stack = vec![(ENTRY, None)];
+    while let Some((block, done)) = stack.pop() {
+        if let Some(expressions) = done {
+            for expression in expressions {
+                numbering.results.remove(&expression);
+            }
+            continue;
+        }
+        let mut expressions = vec![];
+        let old = std::mem::take(&mut cfg.blocks[block].lines);
+        let mut lines = vec![];
+        let mut i = 0;
+        while i < old.len() {
+            let line = &old[i];
+            let (expression, dest, length) = match (line, old.get(i + 1)) {
+                (Line::Move(source, dest), Some(update))
+                    if update.updates_dest() && update.dest() == Some(dest) =>
+                {
+                    let mut operands = vec![*source];
+                    operands.extend(update.operands().into_iter().copied());
+                    (numbering.expression(update, operands), *dest, 2)
+                }
+                (Line::Move(source, dest), _) if same_class(source, dest) => {
+                    if dest.id().is_some() {
+                        let source = numbering.canonical(source);
+                        numbering.copies.insert(*dest, source);
+                    }
+                    lines.push(line.clone());
+                    i += 1;
+                    continue;
+                }
+                // Moves between types convert, so they are computations too
+                _ if is_pure(line) || matches!(line, Line::Move(..)) => {
+                    let operands = line.operands().into_iter().copied().collect();
+                    (
+                        numbering.expression(line, operands),
+                        *line.dest().unwrap(),
+                        1,
+                    )
+                }
+                _ => {
+                    lines.push(line.clone());
+                    i += 1;
+                    continue;
+                }
+            };
+            match numbering.results.get(&expression) {
+                Some(result) => {
+                    numbering.copies.insert(dest, numbering.canonical(result));
+                    lines.push(Line::Move(*result, dest));
+                }
+                None => {
+                    lines.extend(old[i..i + length].iter().cloned());
+                    numbering.results.insert(expression.clone(), dest);
+                    expressions.push(expression);
+                }
+            }
+            i += length;
+        }
+        cfg.blocks[block].lines = lines;
+        stack.push((block, Some(expressions)));
+        for child in children[block].iter().rev() {
+            stack.push((*child, None));
+        }
+    }
+}
+
+struct Numbering {
+    // The value each copied variable has, which is never itself a copy
+    copies: HashMap<Address, Address>,
+    // The variable holding each expression computed in the dominators
+    results: HashMap<Expression, Address>,
+}
+impl Numbering {
+    fn canonical(&self, address: &Address) -> Address {
+        *self.copies.get(address).unwrap_or(address)
+    }
+    fn expression(&self, line: &Line, operands: Vec<Address>) -> Expression {
+        let mut operands: Vec<Address> = operands.iter().map(|a| self.canonical(a)).collect();
+        if is_commutative(line) {
+            operands.sort();
+        }
+        let size = match line {
+            Line::SignExtend(_, _, size) | Line::ZeroExtend(_, _, size) => *size,
+            _ => 0,
+        };
+        let dest = renamed(line.dest().unwrap(), 0);
+        (std::mem::discriminant(line), operands, size, dest)
+    }
+}
+
+// Lines whose result only depends on their operands, other than moves and
+// the lines that update in place
+fn is_pure(line: &Line) -> bool {
+    matches!(
+        line,
+        Line::SignExtend(..)
+            | Line::ZeroExtend(..)
+            | Line::Equal(..)
+            | Line::NotEqual(..)
+            | Line::Less(..)
+            | Line::LessOrEqual(..)
+            | Line::Greater(..)
+            | Line::GreaterOrEqual(..)
+            | Line::UnsignedLess(..)
+            | Line::UnsignedLessOrEqual(..)
+            | Line::UnsignedGreater(..)
+            | Line::UnsignedGreaterOrEqual(..)
+            | Line::IntToFloat(..)
+            | Line::UnsignedToFloat(..)
+            | Line::FloatToInt(..)
+            | Line::FloatToUnsigned(..)
+            | Line::ConvertFloat(..)
+    )
+}
+
+fn is_commutative(line: &Line) -> bool {
+    matches!(
+        line,
+        Line::Add(..)
+            | Line::Multiply(..)
+            | Line::And(..)
+            | Line::Or(..)
+            | Line::Xor(..)
+            | Line::Equal(..)
+            | Line::NotEqual(..)
+    )
+}
@@ -0,0 +1,104 @@
+use super::super::cfg::Cfg;
+use super::super::tac::{Address, Int, Line};
+
+// Rewrites integer lines of a function in SSA form whose result follows from
+// one of their operands alone, like x + 0 or x - x, as moves, and
+// multiplications and unsigned divisions by powers of two as shifts.
+// Floating point lines stay, as x + 0.0 is not x when x is -0.0.
+pub fn run(cfg: &mut Cfg) {
+    for block in cfg.blocks.iter_mut() {
+        let old = std::mem::take(&mut block.lines);
+        let mut i = 0;
+        while i < old.len() {
+            match (&old[i], old.get(i + 1)) {
+                (Line::Move(source, dest), Some(update))
+                    if update.updates_dest() && update.dest() == Some(dest) =>
+                {
+                    block.lines.append(&mut simplify(*source, update));
+                    i += 2;
+                }
+                (line, _) => {
+                    block.lines.push(line.clone());
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+// The lines giving the destination of an update the value of the update
+// applied to the source
+fn simplify(source: Address, update: &Line) -> Vec<Line> {
+    let dest = *update.dest().unwrap();
+    let unchanged = vec![Line::Move(source, dest), update.clone()];
+    let operand = match update.operands().first() {
+        Some(operand) if !dest.is_float() => **operand,
+        _ => return unchanged,
+    };
+    let copy = |value: Address| vec![Line::Move(value, dest)];
+    let is =
+        |address: &Address, value: u64| matches!(address, Address::Constant(v, _) if *v == value);
+    let zero = Address::Constant(0, dest.int().unwrap());
+    match update {
+        Line::Add(..) | Line::Or(..) | Line::Xor(..) if is(&source, 0) => copy(operand),
+        Line::Add(..)
+        | Line::Subtract(..)
+        | Line::Or(..)
+        | Line::Xor(..)
+        | Line::ShiftLeft(..)
+        | Line::ShiftRight(..)
+        | Line::UnsignedShiftRight(..)
+            if is(&operand, 0) =>
+        {
+            copy(source)
+        }
+        Line::Subtract(..) | Line::Xor(..) if source == operand => copy(zero),
+        Line::And(..) | Line::Or(..) if source == operand => copy(source),
+        Line::Multiply(..) | Line::And(..) if is(&source, 0) || is(&operand, 0) => copy(zero),
+        Line::ShiftLeft(..) | Line::ShiftRight(..) | Line::UnsignedShiftRight(..)
+            if is(&source, 0) =>
+        {
+            copy(zero)
+        }
+        Line::Multiply(..) if is(&source, 1) => copy(operand),
+        Line::And(..) if is(&source, u64::MAX) => copy(operand),
+        Line::Multiply(..) | Line::Divide(..) | Line::UnsignedDivide(..) if is(&operand, 1) => {
+            copy(source)
+        }
+        Line::And(..) if is(&operand, u64::MAX) => copy(source),
+        Line::Remainder(..) | Line::UnsignedRemainder(..) if is(&operand, 1) => copy(zero),
+        Line::Multiply(..) => match (power(&source), power(&operand)) {
+            (_, Some(shift)) => vec![
+                Line::Move(source, dest),
+                Line::ShiftLeft(dest, Address::Constant(shift, Int::LONG)),
+            ],
+            (Some(shift), _) => vec![
+                Line::Move(operand, dest),
+                Line::ShiftLeft(dest, Address::Constant(shift, Int::LONG)),
+            ],
+            _ => unchanged,
+        },
+        Line::UnsignedDivide(..) | Line::UnsignedRemainder(..) => match power(&operand) {
+            Some(shift) if matches!(update, Line::UnsignedDivide(..)) => vec![
+                Line::Move(source, dest),
+                Line::UnsignedShiftRight(dest, Address::Constant(shift, Int::LONG)),
+            ],
+            Some(shift) => vec![
+                Line::Move(source, dest),
+                Line::And(dest, Address::Constant((1 << shift) - 1, Int::LONG)),
+            ],
+            None => unchanged,
+        },
+        _ => unchanged,
+    }
+}
+
+// The power of two a constant is
+fn power(address: &Address) -> Option<u64> {
+    match address {
+        Address::Constant(value, _) if value.is_power_of_two() => {
+            Some(value.trailing_zeros() as u64)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,62 @@
+use super::super::ssa::renamed;
+use super::super::tac::{Address, Function, Line, Program};
+use super::inline::fits;
+
+// Turns calls of functions to themselves whose result is returned right away
+// into jumps back to the start of the body, after copying the arguments to
+// the parameters. The recursion becomes a loop, which needs no stack. A
+// result written to a narrow variable is truncated first, so that call stays.
+// The callee may be given the address of a stack slot, which the next
+// iteration would reuse, so functions with them are left alone.
+pub fn run(program: &mut Program) {
+    for (name, function) in program.functions.iter_mut() {
+        if !function.slots().is_empty() {
+            continue;
+        }
+        let mut start = None;
+        let mut i = 0;
+        while i + 1 < function.body.len() {
+            match (&function.body[i], &function.body[i + 1]) {
+                (Line::Call(Address::Global(callee), args, dest), Line::Return(value))
+                    if callee == name
+                        && value == dest
+                        && dest.is_whole()
+                        && args.len() == function.params.len()
+                        && args
+                            .iter()
+                            .zip(function.params.iter())
+                            .all(|(a, p)| fits(a, p)) =>
+                {
+                    let args = args.clone();
+                    let start = *start.get_or_insert_with(|| function.jump_label());
+                    let lines = jump(function, &args, start);
+                    let length = lines.len();
+                    function.body.splice(i..i + 2, lines);
+                    i += length;
+                }
+                _ => i += 1,
+            }
+        }
+        if let Some(start) = start {
+            function.body.insert(0, Line::Label(start));
+        }
+    }
+}
+
+// Copies the arguments to the parameters and jumps to the start. The
+// arguments can read the parameters, so they all go through new variables
+// first.
+fn jump(function: &mut Function, args: &[Address], start: Address) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut copies = vec![];
+    for (arg, param) in args.iter().zip(function.params.clone()) {
+        let copy = renamed(&param, function.var_label());
+        lines.push(Line::Move(*arg, copy));
+        copies.push((copy, param));
+    }
+    for (copy, param) in copies {
+        lines.push(Line::Move(copy, param));
+    }
+    lines.push(Line::Jump(start));
+    lines
+}
@@ -0,0 +1,12 @@
+use std::collections::HashSet;
+
+use super::super::callgraph;
+use super::super::tac::Program;
+
+// Removes the static functions no chain of calls reaches from a function
+// other files can call
+pub fn run(program: &mut Program) {
+    let graph = callgraph::build(program);
+    let reachable: HashSet<String> = graph.reachable(program).into_iter().cloned().collect();
+    program.functions.retain(|name, _| reachable.contains(name));
+}
@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::cfg::{Cfg, ENTRY, EXIT, Phi, dominator_tree};
+use super::tac::{Address, Function, Line};
+
+// Splits the body of the function into a control flow graph in SSA form, so
+// every variable is written by a single line or phi. Lines that update their
+// destination in place, like Add, get a copy of the old value to update.
+pub fn construct(function: &mut Function) -> Cfg {
+    let mut cfg = Cfg::new(std::mem::take(&mut function.body));
+    let idom = cfg.dominators();
+    let frontiers = cfg.dominance_frontiers(&idom);
+    // Blocks writing each variable, kept in order so the new names come out
+    // the same every time
+    let mut writes: BTreeMap<u64, (Address, Vec<usize>)> = BTreeMap::new();
+    for param in function.params.iter() {
+        writes.insert(param.id().unwrap(), (*param, vec![ENTRY]));
+    }
+    // Only variables read before they are written in some block can need a
+    // phi, as the others never live across blocks
+    let mut crossing = HashSet::new();
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        let mut written = HashSet::new();
+        for line in block.lines.iter() {
+            for id in line.uses().into_iter().filter_map(Address::id) {
+                if !written.contains(&id) {
+                    crossing.insert(id);
+                }
+            }
+            if let Some(dest) = line.dest()
+                && let Some(id) = dest.id()
+            {
+                written.insert(id);
+                writes.entry(id).or_insert((*dest, vec![])).1.push(i);
+            }
+        }
+    }
+    for (id, (address, blocks)) in writes.iter() {
+        if !crossing.contains(id) {
+            continue;
+        }
+        let mut placed = HashSet::new();
+        let mut worklist = blocks.clone();
+        while let Some(block) = worklist.pop() {
+            for &frontier in frontiers[block].iter() {
+                if frontier != EXIT && placed.insert(frontier) {
+                    let args = cfg.blocks[frontier].predecessors.len();
+                    cfg.blocks[frontier].phis.push(Phi {
+                        dest: *address,
+                        args: vec![undefined(address); args],
+                    });
+                    worklist.push(frontier);
+                }
+            }
+        }
+    }
+    let mut renamer = Renamer {
+        function,
+        stacks: HashMap::new(),
+        written: HashSet::new(),
+    };
+    for param in renamer.function.params.clone() {
+        renamer.written.insert(param.id().unwrap());
+        renamer.stacks.insert(param.id().unwrap(), vec![param]);
+    }
+    renamer.rename(&mut cfg, &idom);
+    cfg
+}
+
+// Turns the graph back into the body of the function. Each phi becomes copies
+// at the end of its predecessors. Edges from blocks with two successors are
+// split first, so the copies only happen on the edge that leads to the phi.
+pub fn destruct(function: &mut Function, mut cfg: Cfg) {
+    let labels: HashMap<Address, usize> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(i, block)| match block.lines.first() {
+            Some(Line::Label(label)) => Some((*label, i)),
+            _ => None,
+        })
+        .collect();
+    let first = cfg.blocks[ENTRY].successors[0];
+    let mut body = copies(function, &cfg, ENTRY, first);
+    // Split edges jumped to from the middle of the body wait for a place
+    // that is not fallen through
+    let mut pending = vec![];
+    for i in EXIT + 1..cfg.blocks.len() {
+        let mut lines = std::mem::take(&mut cfg.blocks[i].lines);
+        let next = if i + 1 < cfg.blocks.len() {
+            i + 1
+        } else {
+            EXIT
+        };
+        match lines.pop() {
+            Some(Line::Jump(label)) => {
+                lines.append(&mut copies(function, &cfg, i, labels[&label]));
+                lines.push(Line::Jump(label));
+            }
+            Some(mut line @ (Line::JumpIfZero(..) | Line::JumpIfNotZero(..))) => {
+                let (Line::JumpIfZero(_, label) | Line::JumpIfNotZero(_, label)) = &mut line else {
+                    unreachable!()
+                };
+                let target = labels[label];
+                if !cfg.blocks[target].phis.is_empty() {
+                    let split = function.jump_label();
+                    pending.push(Line::Label(split));
+                    pending.append(&mut copies(function, &cfg, i, target));
+                    pending.push(Line::Jump(*label));
+                    *label = split;
+                }
+                lines.push(line);
+                if !cfg.blocks[next].phis.is_empty() {
+                    // Another predecessor jumps there, so it has a label
+                    let Some(Line::Label(label)) = cfg.blocks[next].lines.first() else {
+                        unreachable!()
+                    };
+                    let label = *label;
+                    lines.append(&mut copies(function, &cfg, i, next));
+                    lines.push(Line::Jump(label));
+                }
+            }
+            Some(line @ Line::Return(_)) => lines.push(line),
+            last => {
+                lines.extend(last);
+                lines.append(&mut copies(function, &cfg, i, next));
+            }
+        }
+        body.append(&mut lines);
+        if matches!(body.last(), Some(Line::Jump(_)) | Some(Line::Return(_))) {
+            body.append(&mut pending);
+        }
+    }
+    if !pending.is_empty() {
+        let end = function.jump_label();
+        body.push(Line::Jump(end));
+        body.append(&mut pending);
+        body.push(Line::Label(end));
+    }
+    function.body = body;
+}
+
+struct Renamer<'a> {
+    function: &'a mut Function,
+    // The names of each variable's values, the innermost last
+    stacks: HashMap<u64, Vec<Address>>,
+    written: HashSet<u64>,
+}
+impl Renamer<'_> {
+    // Name for a new value of the variable. Its first value keeps the name.
+    fn fresh(&mut self, address: &Address) -> Address {
+        let id = address.id().unwrap();
+        let name = match self.written.insert(id) {
+            true => *address,
+            false => renamed(address, self.function.var_label()),
+        };
+        self.stacks.entry(id).or_default().push(name);
+        name
+    }
+    // Reads before any write keep the original name
+    fn current(&self, address: &Address) -> Address {
+        match address.id().and_then(|id| self.stacks.get(&id)?.last()) {
+            Some(name) => *name,
+            None => *address,
+        }
+    }
+    // Walks the dominator tree, so the values in scope are the ones written
+    // in the dominators of a block
+    fn rename(&mut self, cfg: &mut Cfg, idom: &[Option<usize>]) {
+        let children = dominator_tree(idom);
+        let originals: Vec<Vec<Address>> = cfg
+            .blocks
+            .iter()
+            .map(|block| block.phis.iter().map(|phi| phi.dest).collect())
+            .collect();
+        // Blocks to visit, and the variables to pop once they are done
+        let mut stack = vec![(ENTRY, None)];
+        while let Some((block, done)) = stack.pop() {
+            if let Some(ids) = done {
+                for id in ids {
+                    self.stacks.get_mut(&id).unwrap().pop();
+                }
+                continue;
+            }
+            let mut ids = vec![];
+            for i in 0..cfg.blocks[block].phis.len() {
+                let dest = cfg.blocks[block].phis[i].dest;
+                cfg.blocks[block].phis[i].dest = self.fresh(&dest);
+                ids.push(dest.id().unwrap());
+            }
+            let mut lines = vec![];
+            for mut line in std::mem::take(&mut cfg.blocks[block].lines) {
+                for operand in line.operands_mut() {
+                    *operand = self.current(operand);
+                }
+                if let Some(dest) = line.dest().copied()
+                    && let Some(id) = dest.id()
+                {
+                    let old = self.current(&dest);
+                    let new = self.fresh(&dest);
+                    if line.updates_dest() {
+                        lines.push(Line::Move(old, new));
+                    }
+                    *line.dest_mut().unwrap() = new;
+                    ids.push(id);
+                }
+                lines.push(line);
+            }
+            cfg.blocks[block].lines = lines;
+            for successor in cfg.blocks[block].successors.clone() {
+                let pred = cfg.blocks[successor]
+                    .predecessors
+                    .iter()
+                    .position(|pred| *pred == block)
+                    .unwrap();
+                for (phi, original) in originals[successor].iter().enumerate() {
+                    let arg = match self.stacks.get(&original.id().unwrap()) {
+                        Some(names) if !names.is_empty() => *names.last().unwrap(),
+                        _ => undefined(original),
+                    };
+                    cfg.blocks[successor].phis[phi].args[pred] = arg;
+                }
+            }
+            stack.push((block, Some(ids)));
+            for child in children[block].iter().rev() {
+                stack.push((*child, None));
+            }
+        }
+    }
+}
+
+// The copies on the edge between two blocks that give the phis of the second
+// their values, in an order where none overwrites a value another still reads
+fn copies(function: &mut Function, cfg: &Cfg, from: usize, to: usize) -> Vec<Line> {
+    let pred = cfg.blocks[to]
+        .predecessors
+        .iter()
+        .position(|pred| *pred == from)
+        .unwrap();
+    let mut copies: Vec<(Address, Address)> = cfg.blocks[to]
+        .phis
+        .iter()
+        .map(|phi| (phi.args[pred], phi.dest))
+        .filter(|(source, dest)| source != dest)
+        .collect();
+    let mut lines = vec![];
+    while !copies.is_empty() {
+        match copies
+            .iter()
+            .position(|(_, dest)| !copies.iter().any(|(source, _)| source == dest))
+        {
+            Some(i) => {
+                let (source, dest) = copies.remove(i);
+                lines.push(Line::Move(source, dest));
+            }
+            None => {
+                // Every destination is still read, so the copies form cycles.
+                // Saving one destination breaks its cycle.
+                let saved = copies[0].1;
+                let temp = renamed(&saved, function.var_label());
+                lines.push(Line::Move(saved, temp));
+                for (source, _) in copies.iter_mut() {
+                    if *source == saved {
+                        *source = temp;
+                    }
+                }
+            }
+        }
+    }
+    lines
+}
+
+// The address of a new variable that holds the same kind of value
+pub fn renamed(address: &Address, id: u64) -> Address {
+    match address {
+        Address::FloatVariable(_, size) => Address::FloatVariable(id, *size),
+        _ => Address::Variable(id, address.int().unwrap()),
+    }
+}
+
+// Value of a phi argument on a path where the variable was never written
+fn undefined(address: &Address) -> Address {
+    match address {
+        Address::FloatVariable(_, size) => Address::FloatConstant(0, *size),
+        _ => Address::Constant(0, address.int().unwrap()),
+    }
+}
@@ -1,62 +1,557 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
 
 use super::super::parser::ast;
+use super::super::parser::symbols::Storage;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+// Width in bytes and signedness of an integer value
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub struct Int {
+    pub size: u64,
+    pub signed: bool,
+}
+impl Int {
+    // The type of integer operands written without one
+    pub const LONG: Int = Int {
+        size: 8,
+        signed: true,
+    };
+    // The value of the low bytes of a value as this type, extended to 64
+    // bits, which is how every integer value is kept
+    pub fn extend(&self, value: u64) -> u64 {
+        let shift = 64 - 8 * self.size.min(8);
+        match self.signed {
+            true => (((value << shift) as i64) >> shift) as u64,
+            false => (value << shift) >> shift,
+        }
+    }
+}
+impl std::fmt::Display for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.signed { 'i' } else { 'u' };
+        write!(f, "{}{}", sign, 8 * self.size)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 pub enum Address {
-    Constant(u64),
-    Variable(u64),
+    Constant(u64, Int), // Value already extended from its type
+    Variable(u64, Int),
+    FloatConstant(u64, u64), // Bit pattern of the value, and its size
+    FloatVariable(u64, u64), // Kept in a floating point register, with the size of its value
+    Global(&'static str),    // A global symbol, named through symbol()
+    Slot(u64),               // 8 bytes in the frame of the function, whose address is the value
+    Label(u64),              // A place in the function that jumps go to
+}
+impl Address {
+    // Whether the address holds a value lines can compute with. Globals and
+    // labels are only named by the lines that use them, and a stack slot
+    // only gives its address to moves, loads and stores.
+    pub fn is_value(&self) -> bool {
+        matches!(
+            self,
+            Address::Constant(..)
+                | Address::Variable(..)
+                | Address::FloatConstant(..)
+                | Address::FloatVariable(..)
+        )
+    }
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self,
+            Address::FloatConstant(..) | Address::FloatVariable(..)
+        )
+    }
+    // Whether a value written to the address keeps all of its bits, which
+    // an integer type narrower than 64 bits does not
+    pub fn is_whole(&self) -> bool {
+        self.int().is_none_or(|int| int.size == 8)
+    }
+    pub fn id(&self) -> Option<u64> {
+        match self {
+            Address::Variable(id, _) | Address::FloatVariable(id, _) => Some(*id),
+            _ => None,
+        }
+    }
+    // The type of an integer value
+    pub fn int(&self) -> Option<Int> {
+        match self {
+            Address::Constant(_, int) | Address::Variable(_, int) => Some(*int),
+            _ => None,
+        }
+    }
 }
 impl std::fmt::Display for Address {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Address::Constant(value) => write!(f, "${}", value),
-            Address::Variable(id) => write!(f, "%{}", id),
+            Address::Constant(value, Int::LONG) => write!(f, "${}", value),
+            // Only the low bytes are written, as the rest follows from them
+            Address::Constant(value, int) => {
+                let bytes = Int {
+                    signed: false,
+                    ..*int
+                };
+                write!(f, "${}:{}", bytes.extend(*value), int)
+            }
+            Address::Variable(id, Int::LONG) => write!(f, "%{}", id),
+            Address::Variable(id, int) => write!(f, "%{}:{}", id, int),
+            // Debug formatting always includes a '.' or exponent, so these
+            // cannot be mistaken for integer constants. NaNs are written
+            // with their bits, which would otherwise be lost.
+            Address::FloatConstant(bits, 4) if f32::from_bits(*bits as u32).is_nan() => {
+                write!(f, "$NaN({:#x})f", bits)
+            }
+            Address::FloatConstant(bits, _) if f64::from_bits(*bits).is_nan() => {
+                write!(f, "$NaN({:#x})", bits)
+            }
+            Address::FloatConstant(bits, 4) => write!(f, "${:?}f", f32::from_bits(*bits as u32)),
+            Address::FloatConstant(bits, _) => write!(f, "${:?}", f64::from_bits(*bits)),
+            Address::FloatVariable(id, 4) => write!(f, "%f{}", id),
+            Address::FloatVariable(id, _) => write!(f, "%d{}", id),
+            Address::Global(name) => write!(f, "@{}", name),
+            Address::Slot(id) => write!(f, "#{}", id),
+            Address::Label(label) => write!(f, "L{}", label),
         }
     }
 }
 
-#[derive(Debug)]
+// Global symbols are named by addresses, which are Copy, so each name is
+// kept once for the rest of the run
+pub fn symbol(name: &str) -> &'static str {
+    lazy_static! {
+        static ref SYMBOLS: Mutex<HashSet<&'static str>> = Mutex::new(HashSet::new());
+    }
+    let mut symbols = SYMBOLS.lock().unwrap();
+    match symbols.get(name) {
+        Some(symbol) => symbol,
+        None => {
+            let symbol: &'static str = Box::leak(name.into());
+            symbols.insert(symbol);
+            symbol
+        }
+    }
+}
+
+// A line writing an integer variable keeps the low bytes of the value for
+// the variable's type, extended again, so moves between types convert
+#[derive(Debug, PartialEq, Clone)]
 pub enum Line {
-    Add(Address, Address),      // A += B
-    Subtract(Address, Address), // A -= B
-    Return(Address),            // Return A
-    Move(Address, Address),     // Move A into B
+    Add(Address, Address),                             // A += B
+    Subtract(Address, Address),                        // A -= B
+    Multiply(Address, Address),                        // A *= B
+    Divide(Address, Address),                          // A /= B
+    UnsignedDivide(Address, Address),                  // A /= B, unsigned
+    Remainder(Address, Address),                       // A %= B
+    UnsignedRemainder(Address, Address),               // A %= B, unsigned
+    And(Address, Address),                             // A &= B
+    Or(Address, Address),                              // A |= B
+    Xor(Address, Address),                             // A ^= B
+    ShiftLeft(Address, Address),                       // A <<= B
+    ShiftRight(Address, Address),                      // A >>= B, arithmetic
+    UnsignedShiftRight(Address, Address),              // A >>= B, logical
+    Negate(Address),                                   // A = -A
+    Complement(Address),                               // A = ~A
+    Not(Address),                                      // A = !A
+    Return(Address),                                   // Return A
+    Move(Address, Address),                            // Move A into B
+    LoadGlobal(Address, Address),                      // Move global A into B
+    StoreGlobal(Address, Address),                     // Move A into global B
+    SignExtend(Address, Address, u64),                 // Sign extend the low C bytes of A into B
+    ZeroExtend(Address, Address, u64),                 // Zero extend the low C bytes of A into B
+    Call(Address, Vec<Address>, Address),              // Call A with arguments B, result into C
+    Equal(Address, Address, Address),                  // C = A == B
+    NotEqual(Address, Address, Address),               // C = A != B
+    Less(Address, Address, Address),                   // C = A < B
+    LessOrEqual(Address, Address, Address),            // C = A <= B
+    Greater(Address, Address, Address),                // C = A > B
+    GreaterOrEqual(Address, Address, Address),         // C = A >= B
+    UnsignedLess(Address, Address, Address),           // C = A < B, unsigned
+    UnsignedLessOrEqual(Address, Address, Address),    // C = A <= B, unsigned
+    UnsignedGreater(Address, Address, Address),        // C = A > B, unsigned
+    UnsignedGreaterOrEqual(Address, Address, Address), // C = A >= B, unsigned
+    Label(Address),                                    // Jump target
+    Jump(Address),                                     // Jump to label A
+    JumpIfZero(Address, Address),                      // Jump to label B if A is 0
+    JumpIfNotZero(Address, Address),                   // Jump to label B if A is not 0
+    Load(Address, Address, u64),                       // Zero extend C bytes at address A into B
+    Store(Address, Address, u64),                      // Store the low C bytes of A at address B
+    IntToFloat(Address, Address),                      // Signed integer A to floating point B
+    UnsignedToFloat(Address, Address),                 // Unsigned integer A to floating point B
+    FloatToInt(Address, Address),                      // Floating point A to signed integer B
+    FloatToUnsigned(Address, Address),                 // Floating point A to unsigned integer B
+    ConvertFloat(Address, Address),                    // Convert between float and double
+}
+impl Line {
+    // The values the line reads or writes, leaving out the globals, labels
+    // and functions it names
+    pub fn addresses(&self) -> Vec<&Address> {
+        match self {
+            Line::Add(a, b)
+            | Line::Subtract(a, b)
+            | Line::Multiply(a, b)
+            | Line::Divide(a, b)
+            | Line::UnsignedDivide(a, b)
+            | Line::Remainder(a, b)
+            | Line::UnsignedRemainder(a, b)
+            | Line::And(a, b)
+            | Line::Or(a, b)
+            | Line::Xor(a, b)
+            | Line::ShiftLeft(a, b)
+            | Line::ShiftRight(a, b)
+            | Line::UnsignedShiftRight(a, b)
+            | Line::Load(a, b, _)
+            | Line::Store(a, b, _)
+            | Line::Move(a, b)
+            | Line::SignExtend(a, b, _)
+            | Line::ZeroExtend(a, b, _)
+            | Line::IntToFloat(a, b)
+            | Line::UnsignedToFloat(a, b)
+            | Line::FloatToInt(a, b)
+            | Line::FloatToUnsigned(a, b)
+            | Line::ConvertFloat(a, b) => vec![a, b],
+            Line::Equal(a, b, c)
+            | Line::NotEqual(a, b, c)
+            | Line::Less(a, b, c)
+            | Line::LessOrEqual(a, b, c)
+            | Line::Greater(a, b, c)
+            | Line::GreaterOrEqual(a, b, c)
+            | Line::UnsignedLess(a, b, c)
+            | Line::UnsignedLessOrEqual(a, b, c)
+            | Line::UnsignedGreater(a, b, c)
+            | Line::UnsignedGreaterOrEqual(a, b, c) => vec![a, b, c],
+            Line::Return(a)
+            | Line::LoadGlobal(_, a)
+            | Line::StoreGlobal(a, _)
+            | Line::Negate(a)
+            | Line::Complement(a)
+            | Line::Not(a)
+            | Line::JumpIfZero(a, _)
+            | Line::JumpIfNotZero(a, _) => vec![a],
+            Line::Label(_) | Line::Jump(_) => vec![],
+            Line::Call(_, args, dest) => args.iter().chain(std::iter::once(dest)).collect(),
+        }
+    }
+    // Whether the line reads its destination before writing it, like Add
+    pub fn updates_dest(&self) -> bool {
+        matches!(
+            self,
+            Line::Add(..)
+                | Line::Subtract(..)
+                | Line::Multiply(..)
+                | Line::Divide(..)
+                | Line::UnsignedDivide(..)
+                | Line::Remainder(..)
+                | Line::UnsignedRemainder(..)
+                | Line::And(..)
+                | Line::Or(..)
+                | Line::Xor(..)
+                | Line::ShiftLeft(..)
+                | Line::ShiftRight(..)
+                | Line::UnsignedShiftRight(..)
+                | Line::Negate(_)
+                | Line::Complement(_)
+                | Line::Not(_)
+        )
+    }
+    // The address the line writes
+    pub fn dest(&self) -> Option<&Address> {
+        match self {
+            Line::Add(a, _)
+            | Line::Subtract(a, _)
+            | Line::Multiply(a, _)
+            | Line::Divide(a, _)
+            | Line::UnsignedDivide(a, _)
+            | Line::Remainder(a, _)
+            | Line::UnsignedRemainder(a, _)
+            | Line::And(a, _)
+            | Line::Or(a, _)
+            | Line::Xor(a, _)
+            | Line::ShiftLeft(a, _)
+            | Line::ShiftRight(a, _)
+            | Line::UnsignedShiftRight(a, _)
+            | Line::Negate(a)
+            | Line::Complement(a)
+            | Line::Not(a)
+            | Line::Move(_, a)
+            | Line::LoadGlobal(_, a)
+            | Line::SignExtend(_, a, _)
+            | Line::ZeroExtend(_, a, _)
+            | Line::Call(_, _, a)
+            | Line::Equal(_, _, a)
+            | Line::NotEqual(_, _, a)
+            | Line::Less(_, _, a)
+            | Line::LessOrEqual(_, _, a)
+            | Line::Greater(_, _, a)
+            | Line::GreaterOrEqual(_, _, a)
+            | Line::UnsignedLess(_, _, a)
+            | Line::UnsignedLessOrEqual(_, _, a)
+            | Line::UnsignedGreater(_, _, a)
+            | Line::UnsignedGreaterOrEqual(_, _, a)
+            | Line::Load(_, a, _)
+            | Line::IntToFloat(_, a)
+            | Line::UnsignedToFloat(_, a)
+            | Line::FloatToInt(_, a)
+            | Line::FloatToUnsigned(_, a)
+            | Line::ConvertFloat(_, a) => Some(a),
+            Line::Return(_)
+            | Line::StoreGlobal(..)
+            | Line::Label(_)
+            | Line::Jump(_)
+            | Line::JumpIfZero(..)
+            | Line::JumpIfNotZero(..)
+            | Line::Store(..) => None,
+        }
+    }
+    pub fn dest_mut(&mut self) -> Option<&mut Address> {
+        match self {
+            Line::Add(a, _)
+            | Line::Subtract(a, _)
+            | Line::Multiply(a, _)
+            | Line::Divide(a, _)
+            | Line::UnsignedDivide(a, _)
+            | Line::Remainder(a, _)
+            | Line::UnsignedRemainder(a, _)
+            | Line::And(a, _)
+            | Line::Or(a, _)
+            | Line::Xor(a, _)
+            | Line::ShiftLeft(a, _)
+            | Line::ShiftRight(a, _)
+            | Line::UnsignedShiftRight(a, _)
+            | Line::Negate(a)
+            | Line::Complement(a)
+            | Line::Not(a)
+            | Line::Move(_, a)
+            | Line::LoadGlobal(_, a)
+            | Line::SignExtend(_, a, _)
+            | Line::ZeroExtend(_, a, _)
+            | Line::Call(_, _, a)
+            | Line::Equal(_, _, a)
+            | Line::NotEqual(_, _, a)
+            | Line::Less(_, _, a)
+            | Line::LessOrEqual(_, _, a)
+            | Line::Greater(_, _, a)
+            | Line::GreaterOrEqual(_, _, a)
+            | Line::UnsignedLess(_, _, a)
+            | Line::UnsignedLessOrEqual(_, _, a)
+            | Line::UnsignedGreater(_, _, a)
+            | Line::UnsignedGreaterOrEqual(_, _, a)
+            | Line::Load(_, a, _)
+            | Line::IntToFloat(_, a)
+            | Line::UnsignedToFloat(_, a)
+            | Line::FloatToInt(_, a)
+            | Line::FloatToUnsigned(_, a)
+            | Line::ConvertFloat(_, a) => Some(a),
+            Line::Return(_)
+            | Line::StoreGlobal(..)
+            | Line::Label(_)
+            | Line::Jump(_)
+            | Line::JumpIfZero(..)
+            | Line::JumpIfNotZero(..)
+            | Line::Store(..) => None,
+        }
+    }
+    // The addresses the line reads, other than a destination it updates
+    pub fn operands(&self) -> Vec<&Address> {
+        match self {
+            Line::Add(_, b)
+            | Line::Subtract(_, b)
+            | Line::Multiply(_, b)
+            | Line::Divide(_, b)
+            | Line::UnsignedDivide(_, b)
+            | Line::Remainder(_, b)
+            | Line::UnsignedRemainder(_, b)
+            | Line::And(_, b)
+            | Line::Or(_, b)
+            | Line::Xor(_, b)
+            | Line::ShiftLeft(_, b)
+            | Line::ShiftRight(_, b)
+            | Line::UnsignedShiftRight(_, b) => vec![b],
+            Line::Return(a)
+            | Line::Move(a, _)
+            | Line::StoreGlobal(a, _)
+            | Line::SignExtend(a, _, _)
+            | Line::ZeroExtend(a, _, _)
+            | Line::JumpIfZero(a, _)
+            | Line::JumpIfNotZero(a, _)
+            | Line::Load(a, _, _)
+            | Line::IntToFloat(a, _)
+            | Line::UnsignedToFloat(a, _)
+            | Line::FloatToInt(a, _)
+            | Line::FloatToUnsigned(a, _)
+            | Line::ConvertFloat(a, _) => vec![a],
+            Line::Equal(a, b, _)
+            | Line::NotEqual(a, b, _)
+            | Line::Less(a, b, _)
+            | Line::LessOrEqual(a, b, _)
+            | Line::Greater(a, b, _)
+            | Line::GreaterOrEqual(a, b, _)
+            | Line::UnsignedLess(a, b, _)
+            | Line::UnsignedLessOrEqual(a, b, _)
+            | Line::UnsignedGreater(a, b, _)
+            | Line::UnsignedGreaterOrEqual(a, b, _)
+            | Line::Store(a, b, _) => vec![a, b],
+            Line::Call(_, args, _) => args.iter().collect(),
+            Line::Negate(_)
+            | Line::Complement(_)
+            | Line::Not(_)
+            | Line::LoadGlobal(..)
+            | Line::Label(_)
+            | Line::Jump(_) => vec![],
+        }
+    }
+    // Every address the line reads
+    pub fn uses(&self) -> Vec<&Address> {
+        let updated = self.dest().filter(|_| self.updates_dest());
+        self.operands().into_iter().chain(updated).collect()
+    }
+    pub fn operands_mut(&mut self) -> Vec<&mut Address> {
+        match self {
+            Line::Add(_, b)
+            | Line::Subtract(_, b)
+            | Line::Multiply(_, b)
+            | Line::Divide(_, b)
+            | Line::UnsignedDivide(_, b)
+            | Line::Remainder(_, b)
+            | Line::UnsignedRemainder(_, b)
+            | Line::And(_, b)
+            | Line::Or(_, b)
+            | Line::Xor(_, b)
+            | Line::ShiftLeft(_, b)
+            | Line::ShiftRight(_, b)
+            | Line::UnsignedShiftRight(_, b) => vec![b],
+            Line::Return(a)
+            | Line::Move(a, _)
+            | Line::StoreGlobal(a, _)
+            | Line::SignExtend(a, _, _)
+            | Line::ZeroExtend(a, _, _)
+            | Line::JumpIfZero(a, _)
+            | Line::JumpIfNotZero(a, _)
+            | Line::Load(a, _, _)
+            | Line::IntToFloat(a, _)
+            | Line::UnsignedToFloat(a, _)
+            | Line::FloatToInt(a, _)
+            | Line::FloatToUnsigned(a, _)
+            | Line::ConvertFloat(a, _) => vec![a],
+            Line::Equal(a, b, _)
+            | Line::NotEqual(a, b, _)
+            | Line::Less(a, b, _)
+            | Line::LessOrEqual(a, b, _)
+            | Line::Greater(a, b, _)
+            | Line::GreaterOrEqual(a, b, _)
+            | Line::UnsignedLess(a, b, _)
+            | Line::UnsignedLessOrEqual(a, b, _)
+            | Line::UnsignedGreater(a, b, _)
+            | Line::UnsignedGreaterOrEqual(a, b, _)
+            | Line::Store(a, b, _) => vec![a, b],
+            Line::Call(_, args, _) => args.iter_mut().collect(),
+            Line::Negate(_)
+            | Line::Complement(_)
+            | Line::Not(_)
+            | Line::LoadGlobal(..)
+            | Line::Label(_)
+            | Line::Jump(_) => vec![],
+        }
+    }
 }
 impl std::fmt::Display for Line {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Line::Add(a, b) => writeln!(f, "add {}, {}", a, b),
             Line::Subtract(a, b) => writeln!(f, "subtract {}, {}", a, b),
+            Line::Multiply(a, b) => writeln!(f, "multiply {}, {}", a, b),
+            Line::Divide(a, b) => writeln!(f, "divide {}, {}", a, b),
+            Line::UnsignedDivide(a, b) => writeln!(f, "unsigned_divide {}, {}", a, b),
+            Line::Remainder(a, b) => writeln!(f, "remainder {}, {}", a, b),
+            Line::UnsignedRemainder(a, b) => writeln!(f, "unsigned_remainder {}, {}", a, b),
+            Line::And(a, b) => writeln!(f, "and {}, {}", a, b),
+            Line::Or(a, b) => writeln!(f, "or {}, {}", a, b),
+            Line::Xor(a, b) => writeln!(f, "xor {}, {}", a, b),
+            Line::ShiftLeft(a, b) => writeln!(f, "shift_left {}, {}", a, b),
+            Line::ShiftRight(a, b) => writeln!(f, "shift_right {}, {}", a, b),
+            Line::UnsignedShiftRight(a, b) => writeln!(f, "unsigned_shift_right {}, {}", a, b),
+            Line::Negate(a) => writeln!(f, "negate {}", a),
+            Line::Complement(a) => writeln!(f, "complement {}", a),
+            Line::Not(a) => writeln!(f, "not {}", a),
             Line::Return(a) => writeln!(f, "return {}", a),
             Line::Move(a, b) => writeln!(f, "move {}, {}", a, b),
+            Line::LoadGlobal(a, b) => writeln!(f, "load_global {}, {}", a, b),
+            Line::StoreGlobal(a, b) => writeln!(f, "store_global {}, {}", a, b),
+            Line::SignExtend(a, b, c) => writeln!(f, "sign_extend {}, {}, {}", a, b, c),
+            Line::ZeroExtend(a, b, c) => writeln!(f, "zero_extend {}, {}, {}", a, b, c),
+            Line::Call(a, b, c) => {
+                write!(f, "call {}(", a)?;
+                for (i, arg) in b.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                writeln!(f, "), {}", c)
+            }
+            Line::Equal(a, b, c) => writeln!(f, "equal {}, {}, {}", a, b, c),
+            Line::NotEqual(a, b, c) => writeln!(f, "not_equal {}, {}, {}", a, b, c),
+            Line::Less(a, b, c) => writeln!(f, "less {}, {}, {}", a, b, c),
+            Line::LessOrEqual(a, b, c) => writeln!(f, "less_or_equal {}, {}, {}", a, b, c),
+            Line::Greater(a, b, c) => writeln!(f, "greater {}, {}, {}", a, b, c),
+            Line::GreaterOrEqual(a, b, c) => writeln!(f, "greater_or_equal {}, {}, {}", a, b, c),
+            Line::UnsignedLess(a, b, c) => writeln!(f, "unsigned_less {}, {}, {}", a, b, c),
+            Line::UnsignedLessOrEqual(a, b, c) => {
+                writeln!(f, "unsigned_less_or_equal {}, {}, {}", a, b, c)
+            }
+            Line::UnsignedGreater(a, b, c) => writeln!(f, "unsigned_greater {}, {}, {}", a, b, c),
+            Line::UnsignedGreaterOrEqual(a, b, c) => {
+                writeln!(f, "unsigned_greater_or_equal {}, {}, {}", a, b, c)
+            }
+            Line::Label(a) => writeln!(f, "{}:", a),
+            Line::Jump(a) => writeln!(f, "jump {}", a),
+            Line::JumpIfZero(a, b) => writeln!(f, "jump_if_zero {}, {}", a, b),
+            Line::JumpIfNotZero(a, b) => writeln!(f, "jump_if_not_zero {}, {}", a, b),
+            Line::Load(a, b, c) => writeln!(f, "load {}, {}, {}", a, b, c),
+            Line::Store(a, b, c) => writeln!(f, "store {}, {}, {}", a, b, c),
+            Line::IntToFloat(a, b) => writeln!(f, "int_to_float {}, {}", a, b),
+            Line::UnsignedToFloat(a, b) => writeln!(f, "unsigned_to_float {}, {}", a, b),
+            Line::FloatToInt(a, b) => writeln!(f, "float_to_int {}, {}", a, b),
+            Line::FloatToUnsigned(a, b) => writeln!(f, "float_to_unsigned {}, {}", a, b),
+            Line::ConvertFloat(a, b) => writeln!(f, "convert_float {}, {}", a, b),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Scope {
     var_counter: u64,
     jump_counter: u64,
+    variables: HashMap<String, u64>,
 }
 impl Scope {
     pub fn new() -> Self {
         Self {
             var_counter: 0,
             jump_counter: 0,
+            variables: HashMap::new(),
         }
     }
     pub fn var_label(&mut self) -> u64 {
-        if self.var_counter != 0 {
-            self.var_counter += 1;
-        }
-        self.var_counter
+        let label = self.var_counter;
+        self.var_counter += 1;
+        label
     }
-    pub fn jump_label(&mut self) -> u64 {
-        if self.jump_counter != 0 {
-            self.jump_counter += 1;
-        }
-        self.jump_counter
+    pub fn declare_variable(&mut self, name: &str) -> u64 {
+        let var = self.var_label();
+        self.variables.insert(name.to_string(), var);
+        var
+    }
+    pub fn variable(&self, name: &str) -> u64 {
+        // The parser has already rejected undeclared identifiers
+        self.variables[name]
+    }
+    pub fn jump_label(&mut self) -> Address {
+        let label = self.jump_counter;
+        self.jump_counter += 1;
+        Address::Label(label)
     }
 }
 
@@ -72,32 +567,380 @@ fn compile_expression(
     expression.compile_tac(scope, target, strict_target)
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub size: u64,
+    pub value: Option<u64>, // None if only declared here and defined elsewhere
+    pub signed: bool,
+    pub float: bool, // The value is the bit pattern of a float or double
+    pub constant: bool,
+    pub internal: bool,
+}
+impl Global {
+    // Merges a declaration of a static variable into the list of globals,
+    // so repeated (tentative) declarations become a single definition
+    fn declare(globals: &mut Vec<Global>, declaration: &ast::Declaration) {
+        let (symbol, internal, defined) = match &declaration.variable.storage {
+            Storage::Automatic => return,
+            Storage::Static { symbol, internal } => (symbol, *internal, true),
+            Storage::Extern(symbol) => (symbol, false, false),
+        };
+        let data_type = &declaration.variable.data_type;
+        let initializer =
+            declaration
+                .initializer
+                .as_ref()
+                .map(|expression| match data_type.is_float() {
+                    true => data_type.float_bits(expression.evaluate_float().unwrap()),
+                    false => expression.evaluate().unwrap() as u64,
+                });
+        match globals.iter_mut().find(|global| &global.name == symbol) {
+            Some(global) => {
+                if defined {
+                    global.value = initializer.or(global.value).or(Some(0));
+                }
+            }
+            None => globals.push(Global {
+                name: symbol.clone(),
+                size: data_type.size(),
+                signed: data_type.is_signed(),
+                float: data_type.is_float(),
+                value: if defined {
+                    Some(initializer.unwrap_or(0))
+                } else {
+                    None
+                },
+                constant: declaration.variable.constant,
+                internal,
+            }),
+        }
+    }
+}
+impl std::fmt::Display for Global {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.value {
+            Some(value) if self.float => write!(
+                f,
+                "Global {}: size {}, value {}",
+                self.name,
+                self.size,
+                Address::FloatConstant(value, self.size)
+            )?,
+            Some(value) => write!(
+                f,
+                "Global {}: size {}, value ${}",
+                self.name, self.size, value
+            )?,
+            None => write!(f, "Extern {}: size {}", self.name, self.size)?,
+        }
+        if self.float {
+            write!(f, ", float")?;
+        }
+        if !self.signed {
+            write!(f, ", unsigned")?;
+        }
+        if self.constant {
+            write!(f, ", const")?;
+        }
+        if self.internal {
+            write!(f, ", static")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Function {
+    pub params: Vec<Address>,
+    pub internal: bool,
     pub body: Vec<Line>,
     scope: Scope,
 }
+// The scope only matters while a function is built, so it is not compared
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params && self.internal == other.internal && self.body == other.body
+    }
+}
 impl Function {
-    pub fn from_ast(ast: Vec<ast::Statement>) -> Self {
+    pub fn var_label(&mut self) -> u64 {
+        self.scope.var_label()
+    }
+    pub fn jump_label(&mut self) -> Address {
+        self.scope.jump_label()
+    }
+    // The stack slots the body uses, in the order they first appear
+    pub fn slots(&self) -> Vec<u64> {
+        let mut slots = vec![];
+        for address in self.body.iter().flat_map(Line::addresses) {
+            if let Address::Slot(id) = address
+                && !slots.contains(id)
+            {
+                slots.push(*id);
+            }
+        }
+        slots
+    }
+    pub fn from_ast(ast: ast::Function, globals: &mut Vec<Global>) -> Self {
         let mut func = Self {
+            params: vec![],
+            internal: ast.internal,
             body: vec![],
             scope: Scope::new(),
         };
-        for statement in ast {
+        for param in ast.params.iter() {
+            let var = func.scope.declare_variable(&param.name);
+            func.params
+                .push(ast::address(var, &param.variable.data_type));
+        }
+        for statement in ast.body {
             match statement {
-                ast::Statement::DECLARE => todo!(),
-                ast::Statement::EXPRESSION => todo!(),
-                ast::Statement::RETURN(expression) => {
+                ast::Statement::DECLARE(declarations) => {
+                    for declaration in declarations {
+                        if declaration.variable.storage != Storage::Automatic {
+                            Global::declare(globals, &declaration);
+                            continue;
+                        }
+                        let var = func.scope.declare_variable(&declaration.name);
+                        if let Some(expression) = declaration.initializer {
+                            let (mut lines, _) =
+                                compile_expression(expression, &mut func.scope, var, true);
+                            func.body.append(&mut lines);
+                        } else {
+                            // C lets a local be written on only some paths
+                            // to a read, so one without an initializer
+                            // starts at 0 and every variable is written
+                            // before it is read
+                            let var = ast::address(var, &declaration.variable.data_type);
+                            let zero = match var {
+                                Address::FloatVariable(_, size) => Address::FloatConstant(0, size),
+                                _ => Address::Constant(0, var.int().unwrap()),
+                            };
+                            func.body.push(Line::Move(zero, var));
+                        }
+                    }
+                }
+                ast::Statement::EXPRESSION(expression) => {
+                    let var = func.scope.var_label();
+                    let (mut lines, _) =
+                        compile_expression(expression, &mut func.scope, var, false);
+                    func.body.append(&mut lines);
+                }
+                ast::Statement::RETURN(Some(expression)) => {
                     let var = func.scope.var_label();
                     let (mut lines, var) =
                         compile_expression(expression, &mut func.scope, var, false);
                     func.body.append(&mut lines);
                     func.body.push(Line::Return(var));
                 }
+                ast::Statement::RETURN(None) => func
+                    .body
+                    .push(Line::Return(Address::Constant(0, Int::LONG))),
             }
         }
+        // Reaching the closing brace of main returns 0 (C99 5.1.2.2.3). Other
+        // functions have no defined value, so they return 0 as well.
+        if !matches!(func.body.last(), Some(Line::Return(_))) {
+            func.body
+                .push(Line::Return(Address::Constant(0, Int::LONG)));
+        }
         func
     }
+    // Checks that the body is well formed: every label is defined once,
+    // every jump goes to one of them, control cannot run off the end, every
+    // line has operands of kinds it can work on, every variable keeps one
+    // type, and every variable is written on every path to a line that reads
+    // it
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let error = |i: usize, text: String| {
+            Err(VerifyError {
+                line: i + 1,
+                text: format!("{} in '{}'", text, self.body[i].to_string().trim_end()),
+            })
+        };
+        let mut labels = HashMap::new();
+        for (i, line) in self.body.iter().enumerate() {
+            if let Line::Label(label) = line
+                && labels.insert(*label, i).is_some()
+            {
+                return error(i, format!("Label {} defined twice", label));
+            }
+        }
+        let mut types: HashMap<u64, Address> = self
+            .params
+            .iter()
+            .map(|param| (param.id().unwrap(), *param))
+            .collect();
+        // The lines control can go to after each line
+        let mut successors = vec![];
+        for (i, line) in self.body.iter().enumerate() {
+            if let Err(text) = check_kinds(line) {
+                return error(i, text);
+            }
+            for address in line.addresses() {
+                if let Some(id) = address.id()
+                    && let Some(other) = types.insert(id, *address)
+                    && other != *address
+                {
+                    return error(i, format!("{} is also used as {}", address, other));
+                }
+            }
+            let target = match line {
+                Line::Jump(label) | Line::JumpIfZero(_, label) | Line::JumpIfNotZero(_, label) => {
+                    match labels.get(label) {
+                        Some(target) => Some(*target),
+                        None => return error(i, format!("Jump to undefined label {}", label)),
+                    }
+                }
+                _ => None,
+            };
+            successors.push(match line {
+                Line::Return(_) => vec![],
+                Line::Jump(_) => vec![target.unwrap()],
+                Line::JumpIfZero(..) | Line::JumpIfNotZero(..) => vec![target.unwrap(), i + 1],
+                _ => vec![i + 1],
+            });
+        }
+        if self.body.is_empty() {
+            return Err(VerifyError {
+                line: 0,
+                text: "Empty body".to_string(),
+            });
+        }
+        // The variables written on every path to each line, worked out for
+        // the lines control can reach
+        let mut defined: Vec<Option<HashSet<u64>>> = vec![None; self.body.len()];
+        defined[0] = Some(self.params.iter().filter_map(|param| param.id()).collect());
+        let mut work = vec![0];
+        while let Some(i) = work.pop() {
+            let line = &self.body[i];
+            let mut after = defined[i].clone().unwrap();
+            for address in line.uses() {
+                if let Some(id) = address.id()
+                    && !after.contains(&id)
+                {
+                    return error(i, format!("{} is read before it is written", address));
+                }
+            }
+            after.extend(line.dest().and_then(|dest| dest.id()));
+            for &successor in successors[i].iter() {
+                if successor == self.body.len() {
+                    return error(i, "Control runs off the end of the function".to_string());
+                }
+                let changed = match &mut defined[successor] {
+                    Some(before) => {
+                        let size = before.len();
+                        before.retain(|id| after.contains(id));
+                        before.len() != size
+                    }
+                    None => {
+                        defined[successor] = Some(after.clone());
+                        true
+                    }
+                };
+                if changed {
+                    work.push(successor);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether a value can be read as an integer
+fn is_integer(address: &Address) -> bool {
+    matches!(address, Address::Variable(..) | Address::Constant(..))
+}
+
+// Whether a value can be used as the address of memory, which the address
+// of a stack slot is
+fn is_pointer(address: &Address) -> bool {
+    is_integer(address) || matches!(address, Address::Slot(_))
+}
+
+// Whether two values are both integers or both floating point numbers of one
+// size
+fn same_kind(a: &Address, b: &Address) -> bool {
+    match (a, b) {
+        (
+            Address::FloatVariable(_, a) | Address::FloatConstant(_, a),
+            Address::FloatVariable(_, b) | Address::FloatConstant(_, b),
+        ) => a == b,
+        _ => is_integer(a) && is_integer(b),
+    }
+}
+
+// The kinds of addresses a line can work on
+fn check_kinds(line: &Line) -> Result<(), String> {
+    if let Some(dest) = line.dest()
+        && dest.id().is_none()
+    {
+        return Err(format!("{} written, which is not a variable", dest));
+    }
+    for address in line.addresses() {
+        if let Address::Constant(value, int) = address
+            && int.extend(*value) != *value
+        {
+            return Err(format!("Constant {} not extended from its type", value));
+        }
+    }
+    let valid = match line {
+        // Moving a stack slot to an integer variable takes its address
+        Line::Move(Address::Slot(_), b) => is_integer(b),
+        Line::Add(a, b)
+        | Line::Subtract(a, b)
+        | Line::Multiply(a, b)
+        | Line::Divide(a, b)
+        | Line::Move(b, a) => same_kind(a, b),
+        Line::Equal(a, b, c)
+        | Line::NotEqual(a, b, c)
+        | Line::Less(a, b, c)
+        | Line::LessOrEqual(a, b, c)
+        | Line::Greater(a, b, c)
+        | Line::GreaterOrEqual(a, b, c) => same_kind(a, b) && is_integer(c),
+        Line::UnsignedDivide(a, b)
+        | Line::Remainder(a, b)
+        | Line::UnsignedRemainder(a, b)
+        | Line::And(a, b)
+        | Line::Or(a, b)
+        | Line::Xor(a, b)
+        | Line::ShiftLeft(a, b)
+        | Line::ShiftRight(a, b)
+        | Line::UnsignedShiftRight(a, b) => is_integer(a) && is_integer(b),
+        Line::UnsignedLess(a, b, c)
+        | Line::UnsignedLessOrEqual(a, b, c)
+        | Line::UnsignedGreater(a, b, c)
+        | Line::UnsignedGreaterOrEqual(a, b, c) => is_integer(a) && is_integer(b) && is_integer(c),
+        Line::Negate(a) | Line::Complement(a) | Line::Not(a) => is_integer(a),
+        Line::JumpIfZero(a, label) | Line::JumpIfNotZero(a, label) => {
+            is_integer(a) && matches!(label, Address::Label(_))
+        }
+        Line::Label(label) | Line::Jump(label) => matches!(label, Address::Label(_)),
+        Line::SignExtend(a, b, size) | Line::ZeroExtend(a, b, size) => {
+            is_integer(a) && is_integer(b) && matches!(size, 1 | 2 | 4 | 8)
+        }
+        Line::Load(pointer, dest, size) => {
+            is_pointer(pointer) && dest.is_value() && matches!(size, 1 | 2 | 4 | 8)
+        }
+        Line::Store(source, pointer, size) => {
+            source.is_value() && is_pointer(pointer) && matches!(size, 1 | 2 | 4 | 8)
+        }
+        Line::IntToFloat(a, b) | Line::UnsignedToFloat(a, b) => is_integer(a) && b.is_float(),
+        Line::FloatToInt(a, b) | Line::FloatToUnsigned(a, b) => a.is_float() && is_integer(b),
+        Line::ConvertFloat(a, b) => a.is_float() && b.is_float(),
+        Line::Return(a) => a.is_value(),
+        Line::LoadGlobal(global, _) | Line::StoreGlobal(_, global) => {
+            matches!(global, Address::Global(_)) && line.addresses()[0].is_value()
+        }
+        Line::Call(callee, args, _) => {
+            matches!(callee, Address::Global(_)) && args.iter().all(Address::is_value)
+        }
+    };
+    match valid {
+        true => Ok(()),
+        false => Err("Operands of the wrong kind".to_string()),
+    }
 }
 impl std::fmt::Display for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -108,29 +951,430 @@ impl std::fmt::Display for Function {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Program {
-    pub functions: HashMap<String, Function>,
+    pub globals: Vec<Global>,
+    pub functions: IndexMap<String, Function>, // In the order they are defined
 }
 impl Program {
     pub fn from_ast(ast: ast::Program) -> Self {
         let mut program = Self {
-            functions: HashMap::new(),
+            globals: vec![],
+            functions: IndexMap::new(),
         };
+        for declaration in ast.globals.iter() {
+            Global::declare(&mut program.globals, declaration);
+        }
         for func in ast.functions {
-            program
-                .functions
-                .insert(func.name, Function::from_ast(func.body));
+            program.functions.insert(
+                func.name.clone(),
+                Function::from_ast(func, &mut program.globals),
+            );
         }
         program
     }
 }
 impl std::fmt::Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for global in self.globals.iter() {
+            writeln!(f, "{}", global)?;
+        }
         for (name, func) in self.functions.iter() {
-            writeln!(f, "Function {}:", name)?;
+            write!(f, "Function {}(", name)?;
+            for (i, param) in func.params.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", param)?;
+            }
+            write!(f, ")")?;
+            if func.internal {
+                write!(f, " static")?;
+            }
+            writeln!(f, ":")?;
             writeln!(f, "{}", func)?;
         }
         Ok(())
     }
 }
+
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    text: String,
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ParseError on line {}: {}", self.line, self.text)
+    }
+}
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub struct VerifyError {
+    line: usize,
+    text: String,
+}
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "VerifyError on line {}: {}", self.line, self.text)
+    }
+}
+impl std::error::Error for VerifyError {}
+
+// Float constant written by Address's Display, without the '$' or the 'f'
+// of single precision values
+fn parse_float(text: &str, size: u64) -> Option<u64> {
+    if let Some(bits) = text
+        .strip_prefix("NaN(0x")
+        .and_then(|text| text.strip_suffix(')'))
+    {
+        return u64::from_str_radix(bits, 16).ok();
+    }
+    match size {
+        4 => Some(text.parse::<f32>().ok()?.to_bits() as u64),
+        _ => Some(text.parse::<f64>().ok()?.to_bits()),
+    }
+}
+
+// "i32" or "u8", as written by Int's Display
+fn parse_int(text: &str) -> Result<Int, String> {
+    let invalid = || format!("Invalid type '{}'", text);
+    let signed = match text.get(..1) {
+        Some("i") => true,
+        Some("u") => false,
+        _ => return Err(invalid()),
+    };
+    let size = match &text[1..] {
+        "8" => 1,
+        "16" => 2,
+        "32" => 4,
+        "64" => 8,
+        _ => return Err(invalid()),
+    };
+    Ok(Int { size, signed })
+}
+
+fn parse_address(text: &str) -> Result<Address, String> {
+    let invalid = || format!("Invalid address '{}'", text);
+    let (text, int) = match text.split_once(':') {
+        Some((text, int)) => (text, Some(parse_int(int)?)),
+        None => (text, None),
+    };
+    if let Some(value) = text.strip_prefix('$') {
+        if let Ok(value) = value.parse::<u64>() {
+            let int = int.unwrap_or(Int::LONG);
+            let bytes = Int {
+                signed: false,
+                ..int
+            };
+            if bytes.extend(value) != value {
+                return Err(format!("Constant '{}' does not fit its type", text));
+            }
+            return Ok(Address::Constant(int.extend(value), int));
+        }
+        if int.is_some() {
+            return Err(invalid());
+        }
+        // "inf" ends in 'f' as well, so the suffix only marks a float if
+        // what is left is a valid value
+        if let Some(bits) = value
+            .strip_suffix('f')
+            .and_then(|value| parse_float(value, 4))
+        {
+            return Ok(Address::FloatConstant(bits, 4));
+        }
+        return parse_float(value, 8)
+            .map(|bits| Address::FloatConstant(bits, 8))
+            .ok_or_else(invalid);
+    }
+    if text.starts_with('@') || text.starts_with('L') {
+        return match int {
+            Some(_) => Err(invalid()),
+            None if text.starts_with('@') => parse_symbol(text),
+            None => parse_label(text),
+        };
+    }
+    if let Some(id) = text.strip_prefix('#') {
+        return match (id.parse::<u64>(), int) {
+            (Ok(id), None) => Ok(Address::Slot(id)),
+            _ => Err(invalid()),
+        };
+    }
+    let id = text.strip_prefix('%').ok_or_else(invalid)?;
+    let (id, size) = match id.strip_prefix('f') {
+        Some(id) => (id, Some(4)),
+        None => match id.strip_prefix('d') {
+            Some(id) => (id, Some(8)),
+            None => (id, None),
+        },
+    };
+    let id = id.parse::<u64>().map_err(|_| invalid())?;
+    Ok(match (size, int) {
+        (Some(_), Some(_)) => return Err(invalid()),
+        (Some(size), None) => Address::FloatVariable(id, size),
+        (None, int) => Address::Variable(id, int.unwrap_or(Int::LONG)),
+    })
+}
+
+fn parse_symbol(text: &str) -> Result<Address, String> {
+    match text.strip_prefix('@') {
+        Some(name) if !name.is_empty() => Ok(Address::Global(symbol(name))),
+        _ => Err(format!("Invalid symbol '{}'", text)),
+    }
+}
+
+fn parse_label(text: &str) -> Result<Address, String> {
+    text.strip_prefix('L')
+        .and_then(|label| label.parse::<u64>().ok())
+        .map(Address::Label)
+        .ok_or_else(|| format!("Invalid label '{}'", text))
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    text.parse::<u64>()
+        .map_err(|_| format!("Invalid number '{}'", text))
+}
+
+// Comma separated addresses, as in the parameters of a function or the
+// arguments of a call
+fn parse_addresses(text: &str) -> Result<Vec<Address>, String> {
+    match text.is_empty() {
+        true => Ok(vec![]),
+        false => text.split(", ").map(parse_address).collect(),
+    }
+}
+
+fn parse_line(text: &str) -> Result<Line, String> {
+    if let Some(label) = text.strip_suffix(':') {
+        return Ok(Line::Label(parse_label(label)?));
+    }
+    let (name, operands) = text.split_once(' ').unwrap_or((text, ""));
+    if name == "call" {
+        // The arguments contain commas of their own
+        let (function, rest) = operands
+            .split_once('(')
+            .ok_or_else(|| format!("Invalid call '{}'", text))?;
+        let (args, dest) = rest
+            .rsplit_once("), ")
+            .ok_or_else(|| format!("Invalid call '{}'", text))?;
+        return Ok(Line::Call(
+            parse_symbol(function)?,
+            parse_addresses(args)?,
+            parse_address(dest)?,
+        ));
+    }
+    let operands: Vec<&str> = match operands.is_empty() {
+        true => vec![],
+        false => operands.split(", ").collect(),
+    };
+    let wrong = || format!("Wrong operands for '{}' in '{}'", name, text);
+    let one = |line: fn(Address) -> Line| match operands[..] {
+        [a] => Ok(line(parse_address(a)?)),
+        _ => Err(wrong()),
+    };
+    let two = |line: fn(Address, Address) -> Line| match operands[..] {
+        [a, b] => Ok(line(parse_address(a)?, parse_address(b)?)),
+        _ => Err(wrong()),
+    };
+    let three = |line: fn(Address, Address, Address) -> Line| match operands[..] {
+        [a, b, c] => Ok(line(
+            parse_address(a)?,
+            parse_address(b)?,
+            parse_address(c)?,
+        )),
+        _ => Err(wrong()),
+    };
+    let sized = |line: fn(Address, Address, u64) -> Line| match operands[..] {
+        [a, b, c] => Ok(line(parse_address(a)?, parse_address(b)?, parse_number(c)?)),
+        _ => Err(wrong()),
+    };
+    let jump = |line: fn(Address, Address) -> Line| match operands[..] {
+        [a, b] => Ok(line(parse_address(a)?, parse_label(b)?)),
+        _ => Err(wrong()),
+    };
+    match name {
+        "add" => two(Line::Add),
+        "subtract" => two(Line::Subtract),
+        "multiply" => two(Line::Multiply),
+        "divide" => two(Line::Divide),
+        "unsigned_divide" => two(Line::UnsignedDivide),
+        "remainder" => two(Line::Remainder),
+        "unsigned_remainder" => two(Line::UnsignedRemainder),
+        "and" => two(Line::And),
+        "or" => two(Line::Or),
+        "xor" => two(Line::Xor),
+        "shift_left" => two(Line::ShiftLeft),
+        "shift_right" => two(Line::ShiftRight),
+        "unsigned_shift_right" => two(Line::UnsignedShiftRight),
+        "negate" => one(Line::Negate),
+        "complement" => one(Line::Complement),
+        "not" => one(Line::Not),
+        "return" => one(Line::Return),
+        "move" => two(Line::Move),
+        "load_global" => match operands[..] {
+            [a, b] => Ok(Line::LoadGlobal(parse_symbol(a)?, parse_address(b)?)),
+            _ => Err(wrong()),
+        },
+        "store_global" => match operands[..] {
+            [a, b] => Ok(Line::StoreGlobal(parse_address(a)?, parse_symbol(b)?)),
+            _ => Err(wrong()),
+        },
+        "sign_extend" => sized(Line::SignExtend),
+        "zero_extend" => sized(Line::ZeroExtend),
+        "equal" => three(Line::Equal),
+        "not_equal" => three(Line::NotEqual),
+        "less" => three(Line::Less),
+        "less_or_equal" => three(Line::LessOrEqual),
+        "greater" => three(Line::Greater),
+        "greater_or_equal" => three(Line::GreaterOrEqual),
+        "unsigned_less" => three(Line::UnsignedLess),
+        "unsigned_less_or_equal" => three(Line::UnsignedLessOrEqual),
+        "unsigned_greater" => three(Line::UnsignedGreater),
+        "unsigned_greater_or_equal" => three(Line::UnsignedGreaterOrEqual),
+        "jump" => match operands[..] {
+            [a] => Ok(Line::Jump(parse_label(a)?)),
+            _ => Err(wrong()),
+        },
+        "jump_if_zero" => jump(Line::JumpIfZero),
+        "jump_if_not_zero" => jump(Line::JumpIfNotZero),
+        "load" => sized(Line::Load),
+        "store" => sized(Line::Store),
+        "int_to_float" => two(Line::IntToFloat),
+        "unsigned_to_float" => two(Line::UnsignedToFloat),
+        "float_to_int" => two(Line::FloatToInt),
+        "float_to_unsigned" => two(Line::FloatToUnsigned),
+        "convert_float" => two(Line::ConvertFloat),
+        _ => Err(format!("Unknown instruction '{}'", name)),
+    }
+}
+
+// "Global x: size 4, value $5, unsigned" or "Extern x: size 4", as written
+// by Global's Display
+fn parse_global(text: &str) -> Result<Global, String> {
+    let invalid = || format!("Invalid global '{}'", text);
+    let (kind, rest) = text.split_once(' ').ok_or_else(invalid)?;
+    let (name, rest) = rest.split_once(": ").ok_or_else(invalid)?;
+    let mut fields = rest.split(", ");
+    let size = fields
+        .next()
+        .and_then(|field| field.strip_prefix("size "))
+        .ok_or_else(invalid)?;
+    let mut global = Global {
+        name: name.to_string(),
+        size: parse_number(size)?,
+        value: None,
+        signed: true,
+        float: false,
+        constant: false,
+        internal: false,
+    };
+    let value = match kind {
+        "Global" => {
+            let value = fields
+                .next()
+                .and_then(|field| field.strip_prefix("value "))
+                .ok_or_else(invalid)?;
+            Some(parse_address(value)?)
+        }
+        "Extern" => None,
+        _ => return Err(invalid()),
+    };
+    for field in fields {
+        match field {
+            "float" => global.float = true,
+            "unsigned" => global.signed = false,
+            "const" => global.constant = true,
+            "static" => global.internal = true,
+            _ => return Err(format!("Unknown attribute '{}'", field)),
+        }
+    }
+    global.value = match value {
+        Some(Address::Constant(value, Int::LONG)) if !global.float => Some(value),
+        Some(Address::FloatConstant(bits, size)) if global.float && size == global.size => {
+            Some(bits)
+        }
+        Some(_) => return Err(format!("Invalid value for global '{}'", name)),
+        None => None,
+    };
+    Ok(global)
+}
+
+// "Function f(%0, %d1) static:", as written by Program's Display
+fn parse_function_header(text: &str) -> Result<(String, Function), String> {
+    let invalid = || format!("Invalid function header '{}'", text);
+    let rest = text.strip_prefix("Function ").ok_or_else(invalid)?;
+    let (name, rest) = rest.split_once('(').ok_or_else(invalid)?;
+    let (params, rest) = rest.split_once(')').ok_or_else(invalid)?;
+    let internal = match rest {
+        ":" => false,
+        " static:" => true,
+        _ => return Err(invalid()),
+    };
+    let function = Function {
+        params: parse_addresses(params)?,
+        internal,
+        body: vec![],
+        scope: Scope::new(),
+    };
+    Ok((name.to_string(), function))
+}
+
+impl std::str::FromStr for Program {
+    type Err = ParseError;
+
+    // Reads the text written by Program's Display
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut program = Self {
+            globals: vec![],
+            functions: IndexMap::new(),
+        };
+        let mut current: Option<String> = None;
+        for (i, line) in text.lines().enumerate() {
+            let error = |text| ParseError { line: i + 1, text };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with("Global ") || line.starts_with("Extern ") {
+                program.globals.push(parse_global(line).map_err(error)?);
+            } else if line.starts_with("Function ") {
+                let (name, function) = parse_function_header(line).map_err(error)?;
+                if program.functions.contains_key(&name) {
+                    return Err(error(format!("Function '{}' defined twice", name)));
+                }
+                program.functions.insert(name.clone(), function);
+                current = Some(name);
+            } else {
+                let function = match &current {
+                    Some(name) => program.functions.get_mut(name).unwrap(),
+                    None => return Err(error("Instruction outside of a function".to_string())),
+                };
+                function.body.push(parse_line(line).map_err(error)?);
+            }
+        }
+        // New variables and labels have to come after the ones in the text
+        for function in program.functions.values_mut() {
+            for address in function.params.iter().chain(
+                function
+                    .body
+                    .iter()
+                    .flat_map(|line| line.addresses().into_iter()),
+            ) {
+                if let Address::Variable(id, _) | Address::FloatVariable(id, _) = address {
+                    function.scope.var_counter = function.scope.var_counter.max(id + 1);
+                }
+            }
+            for line in function.body.iter() {
+                if let Line::Label(Address::Label(label))
+                | Line::Jump(Address::Label(label))
+                | Line::JumpIfZero(_, Address::Label(label))
+                | Line::JumpIfNotZero(_, Address::Label(label)) = line
+                {
+                    function.scope.jump_counter = function.scope.jump_counter.max(label + 1);
+                }
+            }
+        }
+        Ok(program)
+    }
+}
@@ -4,7 +4,9 @@ use regex::Regex;
 pub enum TokenType {
     PARENTH,
     SEMICOLON,
+    COMMA,
     IDENT,
+    FLOAT,
     INT,
     OPER,
 }
@@ -53,16 +55,23 @@ macro_rules! error {
 
 const WHITESPACE: [char; 3] = ['\n', '\t', ' '];
 
-const TOKENS: [(&str, TokenType); 8] = [
+const TOKENS: [(&str, TokenType); 10] = [
     ("\\{", TokenType::PARENTH),
     ("\\}", TokenType::PARENTH),
     ("\\(", TokenType::PARENTH),
     ("\\)", TokenType::PARENTH),
     (";", TokenType::SEMICOLON),
-    (r"[a-zA-Z]\w*", TokenType::IDENT),
+    (",", TokenType::COMMA),
+    (r"[a-zA-Z_]\w*", TokenType::IDENT),
+    // Has to come before INT, so the integer part is not taken on its own
+    (
+        r"([0-9]*\.[0-9]+|[0-9]+\.[0-9]*)([eE][+-]?[0-9]+)?[fF]?|[0-9]+[eE][+-]?[0-9]+[fF]?",
+        TokenType::FLOAT,
+    ),
     ("[0-9]+", TokenType::INT),
+    // Two character operators come first, so that "<=" is not lexed as "<"
     (
-        r"[-~!+*/]|(&&)|(\|\|)|(==)|(!=)|(<)|(<=)|(>)|(>=)|(=)",
+        r"(&&)|(\|\|)|(==)|(!=)|(<<)|(>>)|(<=)|(>=)|[-~!+*/%<>=&|^]",
         TokenType::OPER,
     ),
 ];
@@ -78,27 +87,24 @@ pub fn get_tokens() -> Vec<(regex::Regex, TokenType)> {
 pub fn lex(text_in: &str) -> Result<Vec<Token>, LexError> {
     let mut text: String = text_in.to_string().clone();
     let mut out = vec![];
-    while text.len() > 0 {
-        if WHITESPACE.contains(&text.chars().nth(0).unwrap()) {
+    while !text.is_empty() {
+        if WHITESPACE.contains(&text.chars().next().unwrap()) {
             text = text[1..text.len()].to_string();
             continue;
         }
         let mut found = false;
         for (regex, token_type) in get_tokens() {
             let opt_match = regex.find(&text);
-            match opt_match {
-                Some(reg_match) => {
-                    if reg_match.start() == 0 {
-                        out.push(Token {
-                            text: reg_match.as_str().to_string(),
-                            token_type: token_type,
-                        });
-                        text = text[reg_match.end()..text.len()].to_string();
-                        found = true;
-                        break;
-                    }
-                }
-                None => (),
+            if let Some(reg_match) = opt_match
+                && reg_match.start() == 0
+            {
+                out.push(Token {
+                    text: reg_match.as_str().to_string(),
+                    token_type,
+                });
+                text = text[reg_match.end()..text.len()].to_string();
+                found = true;
+                break;
             }
         }
         if !found {
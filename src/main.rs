@@ -1,5 +1,4 @@
-#![feature(trait_upcasting)]
-#![feature(let_chains)]
+#![allow(clippy::upper_case_acronyms)]
 
 use lazy_static::lazy_static;
 use std::collections::VecDeque;
@@ -16,7 +15,7 @@ use std::process::Command;
 
 use crate::compiler::asm::Dialect;
 
-const TEXT: &'static str = "
+const TEXT: &str = "
 int main() {
     return 2 + 2;
 }
@@ -32,19 +31,121 @@ lazy_static! {
         },
         Register {
             name: String::from("rdx")
+        },
+        Register {
+            name: String::from("rsi")
+        },
+        Register {
+            name: String::from("rdi")
+        },
+        Register {
+            name: String::from("r8")
+        },
+        Register {
+            name: String::from("r9")
+        },
+        Register {
+            name: String::from("r10")
         }
     ];
+    // xmm15 is left out as the backend's scratch register
+    static ref FLOAT_REGISTERS: Vec<Register> = (0..15)
+        .map(|i| Register {
+            name: format!("xmm{}", i)
+        })
+        .collect();
 }
 
 pub fn main() {
-    let c = lexer::lex(TEXT).unwrap();
-    let parsed = parser::parse_program(VecDeque::from(c)).unwrap();
-    parsed.pretty_print();
-    let tac = compiler::tac::Program::from_ast(parsed);
+    // A source file can be given in place of TEXT. Files ending in .tac hold
+    // three address code, which skips the front end. --interpret runs the
+    // program without going through gcc. --dot writes the control flow graph
+    // of each function in SSA form to out/<function>.dot. -O0 to -O2 pick the
+    // optimizations, -O2 by default, and -f<pass> or -fno-<pass> switch one on
+    // or off. --print-after=<pass> and --print-after-all print the functions
+    // in SSA form after passes. --liveness prints the variables live on entry
+    // to and exit from each block of the optimized functions. -S stops once
+    // the assembly is printed, without writing it out or running gcc.
+    let mut path = None;
+    let mut interpret = false;
+    let mut liveness = false;
+    let mut assemble = true;
+    let mut level = 2;
+    let mut switches = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--interpret" => interpret = true,
+            "--liveness" => liveness = true,
+            "-S" => assemble = false,
+            "-O0" => level = 0,
+            "-O1" => level = 1,
+            "-O2" => level = 2,
+            _ if arg.starts_with('-') => switches.push(arg),
+            _ => path = Some(arg),
+        }
+    }
+    let mut passes = compiler::optimize::PassManager::new(level);
+    for switch in switches {
+        if let Some(name) = switch.strip_prefix("-fno-") {
+            passes.disable(name).unwrap();
+        } else if let Some(name) = switch.strip_prefix("-f") {
+            passes.enable(name).unwrap();
+        } else if let Some(name) = switch.strip_prefix("--print-after=") {
+            passes.print_after(name).unwrap();
+        } else if switch == "--print-after-all" {
+            passes.print_after_all();
+        } else if switch == "--dot" {
+            passes.write_dot();
+        } else {
+            panic!("Unknown option '{}'", switch);
+        }
+    }
+    let text = match &path {
+        Some(path) => std::fs::read_to_string(path).unwrap(),
+        None => TEXT.to_string(),
+    };
+    let mut tac = match &path {
+        Some(path) if path.ends_with(".tac") => text.parse::<compiler::tac::Program>().unwrap(),
+        _ => {
+            let c = lexer::lex(&text).unwrap();
+            let parsed = parser::parse_program(VecDeque::from(c)).unwrap();
+            parsed.pretty_print();
+            compiler::tac::Program::from_ast(parsed)
+        }
+    };
+    passes.run(&mut tac);
     println!("{}", tac);
-    let asm =
-        compiler::asm::GCC::new(compiler::asm::RegisterSet::new(&REGISTERS)).compile_program(&tac);
+    // The printed form has to read back as the same program
+    debug_assert!(
+        tac.to_string()
+            .parse::<compiler::tac::Program>()
+            .is_ok_and(|parsed| parsed == tac)
+    );
+    if liveness {
+        for (name, function) in tac.functions.iter() {
+            println!("Liveness of {}:", name);
+            println!("{}", compiler::liveness::analyze(function));
+        }
+    }
+    if interpret {
+        let outcome = compiler::interpreter::Interpreter::new(&tac).run().unwrap();
+        println!("{}", outcome.output);
+        // Reported like the status of a process, which keeps the low byte
+        println!("exit status: {}", outcome.return_value as u8);
+        return;
+    }
+    let mut gcc = compiler::asm::GCC::new(compiler::asm::RegisterSet::new(
+        &REGISTERS,
+        &FLOAT_REGISTERS,
+    ));
+    if passes.enabled("tail") {
+        gcc.tail_calls();
+    }
+    let asm = gcc.compile_program(&tac);
     println!("{}", asm);
+    if !assemble {
+        return;
+    }
     let mut file = File::create("out/out.s").unwrap();
     file.write_all(asm.as_bytes()).unwrap();
     let message = Command::new("gcc")
@@ -56,6 +157,10 @@ pub fn main() {
         .expect("failed to execute process"); //needs linking - wrong format
     println!("{}", String::from_utf8(message.stdout).unwrap());
     println!("{}", String::from_utf8(message.stderr).unwrap());
+    // Running the program would run the one assembled before
+    if !message.status.success() {
+        panic!("gcc could not assemble out/out.s");
+    }
     let output = Command::new(r#"out/out.exe"#)
         .output()
         .expect("failed to execute process"); //needs linking - wrong format
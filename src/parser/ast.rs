@@ -1,4 +1,6 @@
 use super::super::compiler::tac;
+use super::symbols::{Storage, Variable};
+use super::types::{DataType, FunctionType};
 
 pub trait Type {
     fn pretty_print_at(&self, indent: i64, del: &str);
@@ -14,13 +16,84 @@ pub trait Expression: Type {
         target: u64,
         strict_target: bool,
     ) -> (Vec<tac::Line>, tac::Address);
+    fn data_type(&self) -> DataType;
+    // Value of the expression if it is an integer constant expression
+    fn evaluate(&self) -> Option<i64> {
+        None
+    }
+    // Value of the expression if it is a floating point constant expression
+    fn evaluate_float(&self) -> Option<f64> {
+        None
+    }
+    fn is_constant(&self) -> bool {
+        self.evaluate().is_some() || self.evaluate_float().is_some()
+    }
+    // The pointer operand, if the expression is a dereference and so can be
+    // assigned to
+    fn into_pointer(self: Box<Self>) -> Option<Box<dyn Expression>> {
+        None
+    }
+}
+
+// TAC variable `id` holding a value of the given type
+pub fn address(id: u64, data_type: &DataType) -> tac::Address {
+    match data_type.is_float() {
+        true => tac::Address::FloatVariable(id, data_type.size()),
+        false => tac::Address::Variable(id, int(data_type)),
+    }
+}
+
+// TAC integer type of values of the given type, where void stands for the
+// default 64 bits
+pub fn int(data_type: &DataType) -> tac::Int {
+    match data_type.is_void() {
+        true => tac::Int::LONG,
+        false => tac::Int {
+            size: data_type.size(),
+            signed: data_type.is_signed(),
+        },
+    }
+}
+
+// Integer constant of the given type, in the target if it has to be there
+fn compile_constant(
+    value: i64,
+    data_type: &DataType,
+    target: u64,
+    strict_target: bool,
+) -> (Vec<tac::Line>, tac::Address) {
+    let int = int(data_type);
+    let value = tac::Address::Constant(int.extend(value as u64), int);
+    if strict_target {
+        let target = address(target, data_type);
+        return (vec![tac::Line::Move(value, target)], target);
+    }
+    (vec![], value)
+}
+
+// Converts an expression to the given type, if it is not of that type already
+pub fn convert(expression: Box<dyn Expression>, data_type: &DataType) -> Box<dyn Expression> {
+    let from = expression.data_type();
+    // Types with the same representation need no conversion, except that a
+    // pointer has to keep its own type
+    if from == *data_type
+        || (from.size() == data_type.size()
+            && from.is_signed() == data_type.is_signed()
+            && from.is_float() == data_type.is_float()
+            && !from.is_pointer()
+            && !data_type.is_pointer())
+    {
+        return expression;
+    }
+    Box::new(Convert {
+        value: expression,
+        data_type: data_type.clone(),
+    })
 }
-pub trait Factor: Expression {}
 
 pub struct Const {
     pub value: u64,
 }
-impl Factor for Const {}
 impl Expression for Const {
     fn compile_tac(
         &self,
@@ -28,25 +101,64 @@ impl Expression for Const {
         target: u64,
         strict_target: bool,
     ) -> (Vec<tac::Line>, tac::Address) {
-        if strict_target {
-            return (
-                vec![tac::Line::Move(
-                    tac::Address::Constant(self.value),
-                    tac::Address::Variable(target),
-                )],
-                tac::Address::Variable(target),
-            );
+        compile_constant(self.value as i64, &self.data_type(), target, strict_target)
+    }
+    fn data_type(&self) -> DataType {
+        // Like an unsuffixed decimal literal, take the first type that fits
+        if self.value <= i32::MAX as u64 {
+            DataType::Int
+        } else if self.value <= i64::MAX as u64 {
+            DataType::Long
+        } else {
+            DataType::UnsignedLong
         }
-        (vec![], tac::Address::Constant(self.value))
+    }
+    fn evaluate(&self) -> Option<i64> {
+        Some(self.value as i64)
     }
 }
 impl Type for Const {
     fn pretty_print_at(&self, indent: i64, del: &str) {
-        let prefix = String::from(del.repeat(indent as usize));
+        let prefix = del.repeat(indent as usize);
         println!("{}", prefix + "Const " + &self.value.to_string());
     }
 }
 
+pub struct FloatConst {
+    pub value: f64, // Already rounded to data_type
+    pub data_type: DataType,
+}
+impl Expression for FloatConst {
+    fn compile_tac(
+        &self,
+        _scope: &mut tac::Scope,
+        target: u64,
+        strict_target: bool,
+    ) -> (Vec<tac::Line>, tac::Address) {
+        let value = tac::Address::FloatConstant(
+            self.data_type.float_bits(self.value),
+            self.data_type.size(),
+        );
+        if strict_target {
+            let target = address(target, &self.data_type);
+            return (vec![tac::Line::Move(value, target)], target);
+        }
+        (vec![], value)
+    }
+    fn data_type(&self) -> DataType {
+        self.data_type.clone()
+    }
+    fn evaluate_float(&self) -> Option<f64> {
+        Some(self.value)
+    }
+}
+impl Type for FloatConst {
+    fn pretty_print_at(&self, indent: i64, del: &str) {
+        let prefix = del.repeat(indent as usize);
+        println!("{}FloatConst {:?} ({})", prefix, self.value, self.data_type);
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum UnOpType {
     Negate,     // -
@@ -59,16 +171,16 @@ impl UnOpType {
             "-" => Ok(Self::Negate),
             "~" => Ok(Self::Complement),
             "!" => Ok(Self::Not),
-            _ => Err(super::ParseError::new(&format!(
+            _ => Err(super::ParseError::new(format!(
                 "Unknown operation: {}",
                 oper
             ))),
         }
     }
 }
-impl Into<&'static str> for UnOpType {
-    fn into(self) -> &'static str {
-        match self {
+impl From<UnOpType> for &'static str {
+    fn from(val: UnOpType) -> Self {
+        match val {
             UnOpType::Negate => "-",
             UnOpType::Complement => "~",
             UnOpType::Not => "!",
@@ -80,7 +192,6 @@ pub struct UnOp {
     pub value: Box<dyn Expression>,
     pub oper: UnOpType,
 }
-impl Factor for UnOp {}
 impl Expression for UnOp {
     fn compile_tac(
         &self,
@@ -88,12 +199,75 @@ impl Expression for UnOp {
         target: u64,
         strict_target: bool,
     ) -> (Vec<tac::Line>, tac::Address) {
-        todo!()
+        let data_type = self.data_type();
+        if let Some(value) = self.evaluate() {
+            return compile_constant(value, &data_type, target, strict_target);
+        }
+        if let Some(value) = self.evaluate_float() {
+            return FloatConst { value, data_type }.compile_tac(scope, target, strict_target);
+        }
+        let var = scope.var_label();
+        let (mut out, addr) = self.value.compile_tac(scope, var, false);
+        let dest = address(target, &data_type);
+        // The result of ! is an int, so values of other types are compared
+        // with zero instead
+        if let UnOpType::Not = self.oper
+            && addr.int() != dest.int()
+        {
+            let zero = match addr {
+                tac::Address::FloatConstant(_, size) | tac::Address::FloatVariable(_, size) => {
+                    tac::Address::FloatConstant(0, size)
+                }
+                _ => tac::Address::Constant(0, addr.int().unwrap()),
+            };
+            out.push(tac::Line::Equal(addr, zero, dest));
+            return (out, dest);
+        }
+        if addr != dest {
+            out.push(tac::Line::Move(addr, dest));
+        }
+        out.push(match self.oper {
+            // Multiplying by -1 only flips the sign bit, so -0.0 comes out right
+            UnOpType::Negate if data_type.is_float() => tac::Line::Multiply(
+                dest,
+                tac::Address::FloatConstant(data_type.float_bits(-1.0), data_type.size()),
+            ),
+            UnOpType::Negate => tac::Line::Negate(dest),
+            UnOpType::Complement => tac::Line::Complement(dest),
+            UnOpType::Not => tac::Line::Not(dest),
+        });
+        (out, dest)
+    }
+    fn data_type(&self) -> DataType {
+        match self.oper {
+            UnOpType::Not => DataType::Int,
+            _ => self.value.data_type().promote(),
+        }
+    }
+    fn evaluate(&self) -> Option<i64> {
+        if self.value.data_type().is_float() {
+            return match self.oper {
+                UnOpType::Not => Some((self.value.evaluate_float()? == 0.0) as i64),
+                _ => None,
+            };
+        }
+        let value = self.value.evaluate()?;
+        Some(self.data_type().truncate(match self.oper {
+            UnOpType::Negate => value.wrapping_neg(),
+            UnOpType::Complement => !value,
+            UnOpType::Not => (value == 0) as i64,
+        }))
+    }
+    fn evaluate_float(&self) -> Option<f64> {
+        match self.oper {
+            UnOpType::Negate if self.data_type().is_float() => Some(-self.value.evaluate_float()?),
+            _ => None,
+        }
     }
 }
 impl Type for UnOp {
     fn pretty_print_at(&self, indent: i64, del: &str) {
-        let prefix = String::from(del.repeat(indent as usize));
+        let prefix = del.repeat(indent as usize);
         println!("{}", prefix.clone() + "UnOp " + self.oper.into());
         println!("{}Value:", prefix.clone() + del);
         self.value.pretty_print_at(indent + 2, del);
@@ -102,8 +276,8 @@ impl Type for UnOp {
 
 #[derive(Clone, Copy)]
 pub enum BinOpType {
-    BitwiseOr,       // ||
-    BitWiseAnd,      // &&
+    LogicalOr,       // ||
+    LogicalAnd,      // &&
     NotEqual,        // !=
     Equal,           // ==
     LessThan,        // <
@@ -112,12 +286,20 @@ pub enum BinOpType {
     GreaterThanOrEq, // >=
     Add,             // +
     Subtract,        // -
+    Multiply,        // *
+    Divide,          // /
+    Remainder,       // %
+    BitAnd,          // &
+    BitOr,           // |
+    BitXor,          // ^
+    ShiftLeft,       // <<
+    ShiftRight,      // >>
 }
 impl BinOpType {
     pub fn from_str(oper: &str) -> Result<Self, super::ParseError> {
         match oper {
-            "||" => Ok(Self::BitwiseOr),
-            "&&" => Ok(Self::BitWiseAnd),
+            "||" => Ok(Self::LogicalOr),
+            "&&" => Ok(Self::LogicalAnd),
             "!=" => Ok(Self::NotEqual),
             "==" => Ok(Self::Equal),
             "<" => Ok(Self::LessThan),
@@ -126,18 +308,26 @@ impl BinOpType {
             ">=" => Ok(Self::GreaterThanOrEq),
             "+" => Ok(Self::Add),
             "-" => Ok(Self::Subtract),
-            _ => Err(super::ParseError::new(&format!(
+            "*" => Ok(Self::Multiply),
+            "/" => Ok(Self::Divide),
+            "%" => Ok(Self::Remainder),
+            "&" => Ok(Self::BitAnd),
+            "|" => Ok(Self::BitOr),
+            "^" => Ok(Self::BitXor),
+            "<<" => Ok(Self::ShiftLeft),
+            ">>" => Ok(Self::ShiftRight),
+            _ => Err(super::ParseError::new(format!(
                 "Unknown operation: {}",
                 oper
             ))),
         }
     }
 }
-impl Into<&'static str> for BinOpType {
-    fn into(self) -> &'static str {
-        match self {
-            BinOpType::BitwiseOr => "||",
-            BinOpType::BitWiseAnd => "&&",
+impl From<BinOpType> for &'static str {
+    fn from(val: BinOpType) -> Self {
+        match val {
+            BinOpType::LogicalOr => "||",
+            BinOpType::LogicalAnd => "&&",
             BinOpType::NotEqual => "!=",
             BinOpType::Equal => "==",
             BinOpType::LessThan => "<",
@@ -146,6 +336,14 @@ impl Into<&'static str> for BinOpType {
             BinOpType::GreaterThanOrEq => ">=",
             BinOpType::Add => "+",
             BinOpType::Subtract => "-",
+            BinOpType::Multiply => "*",
+            BinOpType::Divide => "/",
+            BinOpType::Remainder => "%",
+            BinOpType::BitAnd => "&",
+            BinOpType::BitOr => "|",
+            BinOpType::BitXor => "^",
+            BinOpType::ShiftLeft => "<<",
+            BinOpType::ShiftRight => ">>",
         }
     }
 }
@@ -155,6 +353,58 @@ pub struct BinOp {
     pub value_b: Box<dyn Expression>,
     pub oper: BinOpType,
 }
+impl BinOp {
+    fn is_comparison(&self) -> bool {
+        matches!(
+            self.oper,
+            BinOpType::NotEqual
+                | BinOpType::Equal
+                | BinOpType::LessThan
+                | BinOpType::GreaterThan
+                | BinOpType::LessThanOrEq
+                | BinOpType::GreaterThanOrEq
+        )
+    }
+    // || and && only evaluate the right operand if the left one does not
+    // already decide the result, which is then set to 0 or 1
+    fn compile_logical(
+        &self,
+        scope: &mut tac::Scope,
+        target: u64,
+    ) -> (Vec<tac::Line>, tac::Address) {
+        let or = matches!(self.oper, BinOpType::LogicalOr);
+        let (decided, end) = (scope.jump_label(), scope.jump_label());
+        let mut out = vec![];
+        for value in [&self.value_a, &self.value_b] {
+            let var = scope.var_label();
+            let (mut lines, mut addr) = value.compile_tac(scope, var, false);
+            out.append(&mut lines);
+            // Floating point values can only be tested by comparing them
+            if let tac::Address::FloatConstant(_, size) | tac::Address::FloatVariable(_, size) =
+                addr
+            {
+                let test = address(scope.var_label(), &DataType::Int);
+                let zero = tac::Address::FloatConstant(0, size);
+                out.push(tac::Line::NotEqual(addr, zero, test));
+                addr = test;
+            }
+            out.push(match or {
+                true => tac::Line::JumpIfNotZero(addr, decided),
+                false => tac::Line::JumpIfZero(addr, decided),
+            });
+        }
+        let int = int(&DataType::Int);
+        let dest = tac::Address::Variable(target, int);
+        out.extend([
+            tac::Line::Move(tac::Address::Constant(!or as u64, int), dest),
+            tac::Line::Jump(end),
+            tac::Line::Label(decided),
+            tac::Line::Move(tac::Address::Constant(or as u64, int), dest),
+            tac::Line::Label(end),
+        ]);
+        (out, dest)
+    }
+}
 impl Expression for BinOp {
     fn compile_tac(
         &self,
@@ -162,36 +412,174 @@ impl Expression for BinOp {
         target: u64,
         strict_target: bool,
     ) -> (Vec<tac::Line>, tac::Address) {
+        let data_type = self.data_type();
+        if let Some(value) = self.evaluate() {
+            return compile_constant(value, &data_type, target, strict_target);
+        }
+        if let Some(value) = self.evaluate_float() {
+            return FloatConst { value, data_type }.compile_tac(scope, target, strict_target);
+        }
+        if let BinOpType::LogicalOr | BinOpType::LogicalAnd = self.oper {
+            return self.compile_logical(scope, target);
+        }
+        let var = scope.var_label();
+        let (mut out, addr1) = self.value_a.compile_tac(scope, var, false);
+        let var = scope.var_label();
+        let (mut exp2, addr2) = self.value_b.compile_tac(scope, var, false);
+        out.append(&mut exp2);
+        // Both operands have the same type, except for shifts where the left
+        // operand decides the type of the result
+        let operand_type = self.value_a.data_type();
+        let unsigned = !operand_type.is_signed() && !operand_type.is_float();
+        if self.is_comparison() {
+            let c = address(target, &DataType::Int);
+            out.push(match (self.oper, unsigned) {
+                (BinOpType::NotEqual, _) => tac::Line::NotEqual(addr1, addr2, c),
+                (BinOpType::Equal, _) => tac::Line::Equal(addr1, addr2, c),
+                (BinOpType::LessThan, false) => tac::Line::Less(addr1, addr2, c),
+                (BinOpType::LessThan, true) => tac::Line::UnsignedLess(addr1, addr2, c),
+                (BinOpType::GreaterThan, false) => tac::Line::Greater(addr1, addr2, c),
+                (BinOpType::GreaterThan, true) => tac::Line::UnsignedGreater(addr1, addr2, c),
+                (BinOpType::LessThanOrEq, false) => tac::Line::LessOrEqual(addr1, addr2, c),
+                (BinOpType::LessThanOrEq, true) => tac::Line::UnsignedLessOrEqual(addr1, addr2, c),
+                (_, false) => tac::Line::GreaterOrEqual(addr1, addr2, c),
+                (_, true) => tac::Line::UnsignedGreaterOrEqual(addr1, addr2, c),
+            });
+            return (out, c);
+        }
+        // The target may be a named variable that is also read by the right
+        // hand side (x = 1 + x), so only write it once both operands have
+        // been used
+        let dest = if addr2 == address(target, &data_type) {
+            scope.var_label()
+        } else {
+            target
+        };
+        let dest_addr = address(dest, &data_type);
+        if addr1 != dest_addr {
+            out.push(tac::Line::Move(addr1, dest_addr));
+        }
+        out.push(match (self.oper, unsigned) {
+            (BinOpType::Add, _) => tac::Line::Add(dest_addr, addr2),
+            (BinOpType::Subtract, _) => tac::Line::Subtract(dest_addr, addr2),
+            (BinOpType::Multiply, _) => tac::Line::Multiply(dest_addr, addr2),
+            (BinOpType::Divide, false) => tac::Line::Divide(dest_addr, addr2),
+            (BinOpType::Divide, true) => tac::Line::UnsignedDivide(dest_addr, addr2),
+            (BinOpType::Remainder, false) => tac::Line::Remainder(dest_addr, addr2),
+            (BinOpType::Remainder, true) => tac::Line::UnsignedRemainder(dest_addr, addr2),
+            (BinOpType::BitAnd, _) => tac::Line::And(dest_addr, addr2),
+            (BinOpType::BitOr, _) => tac::Line::Or(dest_addr, addr2),
+            (BinOpType::BitXor, _) => tac::Line::Xor(dest_addr, addr2),
+            (BinOpType::ShiftLeft, _) => tac::Line::ShiftLeft(dest_addr, addr2),
+            (BinOpType::ShiftRight, false) => tac::Line::ShiftRight(dest_addr, addr2),
+            (BinOpType::ShiftRight, true) => tac::Line::UnsignedShiftRight(dest_addr, addr2),
+            _ => unreachable!(),
+        });
+        if dest != target {
+            out.push(tac::Line::Move(dest_addr, address(target, &data_type)));
+        }
+        (out, address(target, &data_type))
+    }
+    fn data_type(&self) -> DataType {
         match self.oper {
-            BinOpType::Add => {
-                let var = scope.var_label();
-                let (mut out, addr1) = self.value_a.compile_tac(scope, var, false);
-                let (mut exp2, addr2) = self.value_b.compile_tac(scope, target, false);
-                if let tac::Address::Constant(x) = addr1 && let tac::Address::Constant(y) = addr2 {
-                    // Both constants - calculate at compile time
-                    if strict_target {
-                       (vec![tac::Line::Move(tac::Address::Constant(x+y), tac::Address::Variable(target))], tac::Address::Variable(target))
-                    } else {
-                        (vec![], tac::Address::Constant(x+y))
-                    }
-                } else {
-                    out.append(&mut exp2);
-                    out.push(tac::Line::Add(addr2, addr1));
-                    if strict_target && addr2 != tac::Address::Variable(target) {
-                        out.push(tac::Line::Move(addr2, tac::Address::Variable(target)));
-                        (out, tac::Address::Variable(target))
-                    } else {
-                        (out, addr2)
-                    }
-                }
+            _ if self.is_comparison() => DataType::Int,
+            BinOpType::LogicalOr | BinOpType::LogicalAnd => DataType::Int,
+            BinOpType::ShiftLeft | BinOpType::ShiftRight => self.value_a.data_type(),
+            _ => DataType::common(&self.value_a.data_type(), &self.value_b.data_type()),
+        }
+    }
+    fn evaluate(&self) -> Option<i64> {
+        match self.oper {
+            BinOpType::LogicalOr => {
+                return Some((is_true(&*self.value_a)? || is_true(&*self.value_b)?) as i64);
+            }
+            BinOpType::LogicalAnd => {
+                return Some((is_true(&*self.value_a)? && is_true(&*self.value_b)?) as i64);
             }
-            _ => todo!(),
+            _ => (),
+        }
+        if self.value_a.data_type().is_float() {
+            // Arithmetic on floating point values is done by evaluate_float
+            let a = self.value_a.evaluate_float()?;
+            let b = self.value_b.evaluate_float()?;
+            return match self.oper {
+                BinOpType::NotEqual => Some((a != b) as i64),
+                BinOpType::Equal => Some((a == b) as i64),
+                BinOpType::LessThan => Some((a < b) as i64),
+                BinOpType::GreaterThan => Some((a > b) as i64),
+                BinOpType::LessThanOrEq => Some((a <= b) as i64),
+                BinOpType::GreaterThanOrEq => Some((a >= b) as i64),
+                _ => None,
+            };
+        }
+        let a = self.value_a.evaluate()?;
+        let b = self.value_b.evaluate()?;
+        // Values are kept extended to 64 bits, so unsigned operands compare
+        // correctly as u64. The left operand has the type of both, or of the
+        // result for shifts.
+        let signed = self.value_a.data_type().is_signed();
+        let less = |a: i64, b: i64| match signed {
+            true => a < b,
+            false => (a as u64) < (b as u64),
+        };
+        // Shifting by the width of the type or more is undefined
+        let count = u32::try_from(b)
+            .ok()
+            .filter(|count| *count < 8 * self.data_type().size() as u32);
+        Some(self.data_type().truncate(match self.oper {
+            BinOpType::NotEqual => (a != b) as i64,
+            BinOpType::Equal => (a == b) as i64,
+            BinOpType::LessThan => less(a, b) as i64,
+            BinOpType::GreaterThan => less(b, a) as i64,
+            BinOpType::LessThanOrEq => !less(b, a) as i64,
+            BinOpType::GreaterThanOrEq => !less(a, b) as i64,
+            BinOpType::Add => a.wrapping_add(b),
+            BinOpType::Subtract => a.wrapping_sub(b),
+            BinOpType::Multiply => a.wrapping_mul(b),
+            // Division by zero is undefined, so it is left for run time
+            BinOpType::Divide if signed => a.checked_div(b)?,
+            BinOpType::Divide => (a as u64).checked_div(b as u64)? as i64,
+            BinOpType::Remainder if signed => a.checked_rem(b)?,
+            BinOpType::Remainder => (a as u64).checked_rem(b as u64)? as i64,
+            BinOpType::BitAnd => a & b,
+            BinOpType::BitOr => a | b,
+            BinOpType::BitXor => a ^ b,
+            BinOpType::ShiftLeft => a << count?,
+            BinOpType::ShiftRight if signed => a >> count?,
+            BinOpType::ShiftRight => ((a as u64) >> count?) as i64,
+            BinOpType::LogicalOr | BinOpType::LogicalAnd => unreachable!(),
+        }))
+    }
+    fn evaluate_float(&self) -> Option<f64> {
+        let data_type = self.data_type();
+        if !data_type.is_float() {
+            return None;
         }
+        let a = self.value_a.evaluate_float()?;
+        let b = self.value_b.evaluate_float()?;
+        // Operations on two floats done in double precision round to the
+        // same result as in single precision
+        Some(data_type.round(match self.oper {
+            BinOpType::Add => a + b,
+            BinOpType::Subtract => a - b,
+            BinOpType::Multiply => a * b,
+            BinOpType::Divide => a / b,
+            _ => return None,
+        }))
     }
 }
+
+// Truth value of a constant operand of || or &&
+fn is_true(expression: &dyn Expression) -> Option<bool> {
+    match expression.data_type().is_float() {
+        true => Some(expression.evaluate_float()? != 0.0),
+        false => Some(expression.evaluate()? != 0),
+    }
+}
+
 impl Type for BinOp {
     fn pretty_print_at(&self, indent: i64, del: &str) {
-        let prefix = String::from(del.repeat(indent as usize));
+        let prefix = del.repeat(indent as usize);
         println!(
             "{}BinOp '{}'",
             prefix.clone(),
@@ -204,9 +592,50 @@ impl Type for BinOp {
     }
 }
 
+pub struct Var {
+    pub name: String,
+    pub data_type: DataType,
+    pub storage: Storage,
+}
+impl Expression for Var {
+    fn compile_tac(
+        &self,
+        scope: &mut tac::Scope,
+        target: u64,
+        strict_target: bool,
+    ) -> (Vec<tac::Line>, tac::Address) {
+        let target = address(target, &self.data_type);
+        if let Some(symbol) = self.storage.symbol() {
+            return (
+                vec![tac::Line::LoadGlobal(
+                    tac::Address::Global(tac::symbol(symbol)),
+                    target,
+                )],
+                target,
+            );
+        }
+        let var = address(scope.variable(&self.name), &self.data_type);
+        if strict_target && var != target {
+            return (vec![tac::Line::Move(var, target)], target);
+        }
+        (vec![], var)
+    }
+    fn data_type(&self) -> DataType {
+        self.data_type.clone()
+    }
+}
+impl Type for Var {
+    fn pretty_print_at(&self, indent: i64, del: &str) {
+        let prefix = del.repeat(indent as usize);
+        println!("{}Var {} ({})", prefix, self.name, self.data_type);
+    }
+}
+
 pub struct Assign {
     pub var_name: String,
-    pub expression: Box<dyn Expression>,
+    pub data_type: DataType,
+    pub storage: Storage,
+    pub expression: Box<dyn Expression>, // Already converted to data_type
 }
 impl Expression for Assign {
     fn compile_tac(
@@ -215,46 +644,312 @@ impl Expression for Assign {
         target: u64,
         strict_target: bool,
     ) -> (Vec<tac::Line>, tac::Address) {
-        todo!()
+        if let Some(symbol) = self.storage.symbol() {
+            let (mut out, addr) = self.expression.compile_tac(scope, target, strict_target);
+            out.push(tac::Line::StoreGlobal(
+                addr,
+                tac::Address::Global(tac::symbol(symbol)),
+            ));
+            return (out, addr);
+        }
+        let var = scope.variable(&self.var_name);
+        let (mut out, _) = self.expression.compile_tac(scope, var, true);
+        let (var, target) = (
+            address(var, &self.data_type),
+            address(target, &self.data_type),
+        );
+        if strict_target && var != target {
+            out.push(tac::Line::Move(var, target));
+            return (out, target);
+        }
+        (out, var)
+    }
+    fn data_type(&self) -> DataType {
+        self.data_type.clone()
     }
 }
 impl Type for Assign {
     fn pretty_print_at(&self, indent: i64, del: &str) {
-        let prefix = String::from(del.repeat(indent as usize));
+        let prefix = del.repeat(indent as usize);
         println!("{}", prefix.clone() + "Assign");
         println!("{}", prefix.clone() + del + "Var: " + &self.var_name);
         self.expression.pretty_print_at(indent + 1, del);
     }
 }
 
+pub struct Dereference {
+    pub pointer: Box<dyn Expression>,
+    pub data_type: DataType, // The type pointed to
+}
+impl Expression for Dereference {
+    fn compile_tac(
+        &self,
+        scope: &mut tac::Scope,
+        target: u64,
+        _strict_target: bool,
+    ) -> (Vec<tac::Line>, tac::Address) {
+        let var = scope.var_label();
+        let (mut out, pointer) = self.pointer.compile_tac(scope, var, false);
+        let dest = address(target, &self.data_type);
+        out.push(tac::Line::Load(pointer, dest, self.data_type.size()));
+        (out, dest)
+    }
+    fn data_type(&self) -> DataType {
+        self.data_type.clone()
+    }
+    fn into_pointer(self: Box<Self>) -> Option<Box<dyn Expression>> {
+        Some(self.pointer)
+    }
+}
+impl Type for Dereference {
+    fn pretty_print_at(&self, indent: i64, del: &str) {
+        let prefix = del.repeat(indent as usize);
+        println!("{}Dereference ({})", prefix, self.data_type);
+        self.pointer.pretty_print_at(indent + 1, del);
+    }
+}
+
+// Assignment to the object a pointer points to
+pub struct DereferenceAssign {
+    pub pointer: Box<dyn Expression>,
+    pub data_type: DataType,
+    pub expression: Box<dyn Expression>, // Already converted to data_type
+}
+impl Expression for DereferenceAssign {
+    fn compile_tac(
+        &self,
+        scope: &mut tac::Scope,
+        target: u64,
+        strict_target: bool,
+    ) -> (Vec<tac::Line>, tac::Address) {
+        let var = scope.var_label();
+        let (mut out, pointer) = self.pointer.compile_tac(scope, var, false);
+        // The value does not go straight to the target, which may be the
+        // pointer variable itself (p = *p = 0)
+        let var = scope.var_label();
+        let (mut value_lines, value) = self.expression.compile_tac(scope, var, false);
+        out.append(&mut value_lines);
+        out.push(tac::Line::Store(value, pointer, self.data_type.size()));
+        let target = address(target, &self.data_type);
+        if strict_target && value != target {
+            out.push(tac::Line::Move(value, target));
+            return (out, target);
+        }
+        (out, value)
+    }
+    fn data_type(&self) -> DataType {
+        self.data_type.clone()
+    }
+}
+impl Type for DereferenceAssign {
+    fn pretty_print_at(&self, indent: i64, del: &str) {
+        let prefix = del.repeat(indent as usize);
+        println!("{}DereferenceAssign", prefix);
+        self.pointer.pretty_print_at(indent + 1, del);
+        self.expression.pretty_print_at(indent + 1, del);
+    }
+}
+
+pub struct Convert {
+    pub value: Box<dyn Expression>,
+    pub data_type: DataType,
+}
+impl Expression for Convert {
+    fn compile_tac(
+        &self,
+        scope: &mut tac::Scope,
+        target: u64,
+        strict_target: bool,
+    ) -> (Vec<tac::Line>, tac::Address) {
+        // A cast to void only keeps the side effects of the value
+        if self.data_type.is_void() {
+            return self.value.compile_tac(scope, target, false);
+        }
+        if let Some(value) = self.evaluate() {
+            return compile_constant(value, &self.data_type, target, strict_target);
+        }
+        if let Some(value) = self.evaluate_float() {
+            return FloatConst {
+                value,
+                data_type: self.data_type.clone(),
+            }
+            .compile_tac(scope, target, strict_target);
+        }
+        // The value has another type, so it needs its own variable
+        let from = self.value.data_type();
+        let var = scope.var_label();
+        let (mut out, addr) = self.value.compile_tac(scope, var, false);
+        let dest = address(target, &self.data_type);
+        out.push(match (from.is_float(), self.data_type.is_float()) {
+            (false, false) => tac::Line::Move(addr, dest),
+            (true, true) => tac::Line::ConvertFloat(addr, dest),
+            (false, _) if from == DataType::UnsignedLong => tac::Line::UnsignedToFloat(addr, dest),
+            (false, _) => tac::Line::IntToFloat(addr, dest),
+            (true, _) if self.data_type == DataType::UnsignedLong => {
+                tac::Line::FloatToUnsigned(addr, dest)
+            }
+            (true, _) => tac::Line::FloatToInt(addr, dest),
+        });
+        (out, dest)
+    }
+    fn data_type(&self) -> DataType {
+        self.data_type.clone()
+    }
+    fn evaluate(&self) -> Option<i64> {
+        if self.data_type.is_float() || self.data_type.is_void() {
+            return None;
+        }
+        if self.value.data_type().is_float() {
+            // Out of range conversions are undefined, so any result will do
+            let value = self.value.evaluate_float()?;
+            return Some(self.data_type.truncate(match self.data_type {
+                DataType::UnsignedLong => value as u64 as i64,
+                _ => value as i64,
+            }));
+        }
+        Some(self.data_type.truncate(self.value.evaluate()?))
+    }
+    fn evaluate_float(&self) -> Option<f64> {
+        if !self.data_type.is_float() {
+            return None;
+        }
+        let from = self.value.data_type();
+        if from.is_float() {
+            return Some(self.data_type.round(self.value.evaluate_float()?));
+        }
+        // Converting straight from the integer avoids rounding twice
+        let value = self.value.evaluate()?;
+        Some(match (&self.data_type, from == DataType::UnsignedLong) {
+            (DataType::Float, true) => value as u64 as f32 as f64,
+            (DataType::Float, false) => value as f32 as f64,
+            (_, true) => value as u64 as f64,
+            (_, false) => value as f64,
+        })
+    }
+}
+impl Type for Convert {
+    fn pretty_print_at(&self, indent: i64, del: &str) {
+        let prefix = del.repeat(indent as usize);
+        println!("{}Convert to {}", prefix, self.data_type);
+        self.value.pretty_print_at(indent + 1, del);
+    }
+}
+
+pub struct Call {
+    pub name: String,
+    pub args: Vec<Box<dyn Expression>>, // Already converted to the parameter types
+    pub return_type: DataType,
+}
+impl Expression for Call {
+    fn compile_tac(
+        &self,
+        scope: &mut tac::Scope,
+        target: u64,
+        _strict_target: bool,
+    ) -> (Vec<tac::Line>, tac::Address) {
+        let mut out = vec![];
+        let mut args = vec![];
+        for arg in self.args.iter() {
+            let var = scope.var_label();
+            let (mut lines, addr) = arg.compile_tac(scope, var, false);
+            out.append(&mut lines);
+            args.push(addr);
+        }
+        let dest = address(target, &self.return_type);
+        out.push(tac::Line::Call(
+            tac::Address::Global(tac::symbol(&self.name)),
+            args,
+            dest,
+        ));
+        (out, dest)
+    }
+    fn data_type(&self) -> DataType {
+        self.return_type.clone()
+    }
+}
+impl Type for Call {
+    fn pretty_print_at(&self, indent: i64, del: &str) {
+        let prefix = del.repeat(indent as usize);
+        println!("{}Call {} ({})", prefix, self.name, self.return_type);
+        for arg in self.args.iter() {
+            arg.pretty_print_at(indent + 1, del);
+        }
+    }
+}
+
+pub struct Declaration {
+    pub name: String,
+    pub variable: Variable,
+    pub initializer: Option<Box<dyn Expression>>,
+}
+impl Type for Declaration {
+    fn pretty_print_at(&self, indent: i64, del: &str) {
+        let prefix = del.repeat(indent as usize);
+        let storage = match &self.variable.storage {
+            Storage::Automatic => String::new(),
+            Storage::Static { symbol, .. } => format!(" static {}", symbol),
+            Storage::Extern(symbol) => format!(" extern {}", symbol),
+        };
+        println!(
+            "{}Declare {} ({}{}){}",
+            prefix,
+            self.name,
+            if self.variable.constant { "const " } else { "" },
+            self.variable.data_type,
+            storage
+        );
+        if let Some(exp) = &self.initializer {
+            exp.pretty_print_at(indent + 1, del);
+        }
+    }
+}
+
 pub enum Statement {
-    DECLARE,
-    RETURN(Box<dyn Expression>),
-    EXPRESSION,
+    DECLARE(Vec<Declaration>), // Empty for declarations that only introduce types
+    RETURN(Option<Box<dyn Expression>>),
+    EXPRESSION(Box<dyn Expression>),
 }
 impl Type for Statement {
     fn pretty_print_at(&self, indent: i64, del: &str) {
-        let prefix = String::from(del.repeat(indent as usize));
+        let prefix = del.repeat(indent as usize);
         match self {
-            Self::RETURN(exp) => {
+            Self::RETURN(Some(exp)) => {
                 println!("{}", prefix + "RETURN Statement with value:");
                 exp.pretty_print_at(indent + 1, del);
             }
-            _ => {
-                println!("{}", prefix + "Other Statement")
+            Self::RETURN(None) => {
+                println!("{}", prefix + "RETURN Statement");
+            }
+            Self::DECLARE(declarations) => {
+                println!("{}", prefix + "DECLARE Statement:");
+                for declaration in declarations {
+                    declaration.pretty_print_at(indent + 1, del);
+                }
+            }
+            Self::EXPRESSION(exp) => {
+                println!("{}", prefix + "EXPRESSION Statement:");
+                exp.pretty_print_at(indent + 1, del);
             }
         }
     }
 }
 pub struct Function {
     pub name: String,
+    pub signature: FunctionType,
+    pub params: Vec<Declaration>,
+    pub internal: bool,
     pub body: Vec<Statement>,
 }
 impl Type for Function {
     fn pretty_print_at(&self, indent: i64, del: &str) {
-        let prefix = String::from(del.repeat(indent as usize));
+        let prefix = del.repeat(indent as usize);
         println!("{}", prefix.clone() + "Function");
         println!("{}", prefix.clone() + del + "Name: " + &self.name);
+        println!("{}Type: {}", prefix.clone() + del, self.signature);
+        println!("{}", prefix.clone() + del + "Params: ");
+        for param in &self.params {
+            param.pretty_print_at(indent + 2, del);
+        }
         println!("{}", prefix.clone() + del + "Body: ");
         for statement in &self.body {
             statement.pretty_print_at(indent + 2, del);
@@ -263,12 +958,17 @@ impl Type for Function {
 }
 
 pub struct Program {
+    pub globals: Vec<Declaration>,
     pub functions: Vec<Function>,
 }
 impl Type for Program {
     fn pretty_print_at(&self, indent: i64, del: &str) {
-        let prefix = String::from(del.repeat(indent as usize));
+        let prefix = del.repeat(indent as usize);
         println!("{}", prefix.clone() + "Program");
+        println!("{}", prefix.clone() + del + "Globals: ");
+        for global in &self.globals {
+            global.pretty_print_at(indent + 2, del);
+        }
         println!("{}", prefix.clone() + del + "Functions: ");
         for func in &self.functions {
             func.pretty_print_at(indent + 2, del);
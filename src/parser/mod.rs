@@ -1,19 +1,27 @@
 use crate::lexer::{Token, TokenType};
 
 pub mod ast;
+pub mod symbols;
+pub mod types;
 
 use lazy_static::lazy_static;
 use std::collections::VecDeque;
+use symbols::{Storage, Symbol, SymbolTable, Variable};
+use types::{DataType, FunctionType};
 
 lazy_static! {
     static ref OP_ORDER: Vec<Vec<&'static str>> = {
         vec![
             vec!["||"],
             vec!["&&"],
+            vec!["|"],
+            vec!["^"],
+            vec!["&"],
             vec!["!=", "=="],
             vec!["<", ">", "<=", ">="],
+            vec!["<<", ">>"],
             vec!["+", "-"],
-            vec!["*", "/"],
+            vec!["*", "/", "%"],
         ]
     };
     static ref OP_LEN: i64 = OP_ORDER.len() as i64;
@@ -47,124 +55,709 @@ macro_rules! error {
     }
 }
 
-pub fn parse_program(mut tokens: VecDeque<Token>) -> Result<ast::Program, ParseError> {
-    let mut functions: Vec<ast::Function> = vec![];
-    while tokens.len() > 0 {
-        if tokens[0].text() != "int" {
-            return error!("First token must be int");
+fn expect(tokens: &mut VecDeque<Token>, text: &str) -> Result<Token, ParseError> {
+    match tokens.pop_front() {
+        Some(token) if token.text() == text => Ok(token),
+        Some(token) => error!("Expected '{}' but found '{}'", text, token.text()),
+        None => error!("Expected '{}' but reached end of input", text),
+    }
+}
+
+fn expect_ident(tokens: &mut VecDeque<Token>) -> Result<String, ParseError> {
+    match tokens.pop_front() {
+        Some(token) if token.token_type() == &TokenType::IDENT => Ok(token.text().to_string()),
+        Some(token) => error!("Expected identifier but found '{}'", token.text()),
+        None => error!("Expected identifier but reached end of input"),
+    }
+}
+
+const TYPE_KEYWORDS: [&str; 9] = [
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned",
+];
+
+// Resolves a combination of type keywords such as "unsigned long int"
+fn basic_type(keywords: &[&str]) -> Result<DataType, ParseError> {
+    let count = |keyword: &str| keywords.iter().filter(|k| **k == keyword).count();
+    let (signed, unsigned) = (count("signed"), count("unsigned"));
+    if signed + unsigned > 1 {
+        return error!("Cannot combine signed and unsigned");
+    }
+    let base: Vec<&str> = keywords
+        .iter()
+        .copied()
+        .filter(|k| *k != "signed" && *k != "unsigned")
+        .collect();
+    let data_type = match base.as_slice() {
+        ["void"] if signed + unsigned == 0 => DataType::Void,
+        ["float"] if signed + unsigned == 0 => DataType::Float,
+        ["double"] if signed + unsigned == 0 => DataType::Double,
+        ["long", "double"] | ["double", "long"] => {
+            return error!("long double is not supported");
+        }
+        ["char"] if unsigned == 1 => DataType::UnsignedChar,
+        ["char"] => DataType::Char,
+        ["short"] | ["short", "int"] | ["int", "short"] if unsigned == 1 => DataType::UnsignedShort,
+        ["short"] | ["short", "int"] | ["int", "short"] => DataType::Short,
+        [] | ["int"] if unsigned == 1 => DataType::UnsignedInt,
+        [] | ["int"] => DataType::Int,
+        // long long is the same size as long
+        _ if count("long") >= 1
+            && count("long") <= 2
+            && base.iter().all(|k| *k == "long" || *k == "int")
+            && count("int") <= 1 =>
+        {
+            if unsigned == 1 {
+                DataType::UnsignedLong
+            } else {
+                DataType::Long
+            }
         }
-        if tokens[1].token_type() != &TokenType::IDENT {
-            return error!("Function name not supplied");
+        _ => {
+            return error!(
+                "Invalid combination of type specifiers '{}'",
+                keywords.join(" ")
+            );
+        }
+    };
+    Ok(data_type)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageClass {
+    Typedef,
+    Static,
+    Extern,
+}
+
+pub struct Specifiers {
+    pub data_type: DataType,
+    pub storage_class: Option<StorageClass>,
+    pub constant: bool,
+}
+
+// Whether the token starts a type name. An identifier only starts one if it
+// currently names a typedef.
+fn is_type_name(token: Option<&Token>, symbols: &SymbolTable) -> bool {
+    match token {
+        Some(token) => match token.text() {
+            text if TYPE_KEYWORDS.contains(&text) => true,
+            "enum" | "const" => true,
+            name => symbols.typedef(name).is_some(),
+        },
+        None => false,
+    }
+}
+
+// Whether the tokens start a declaration rather than an expression
+pub fn is_declaration(tokens: &VecDeque<Token>, symbols: &SymbolTable) -> bool {
+    match tokens.front().map(|token| token.text()) {
+        Some("typedef" | "static" | "extern") => true,
+        _ => is_type_name(tokens.front(), symbols),
+    }
+}
+
+// Wraps a type in one pointer for each '*' before a declarator
+fn parse_pointers(tokens: &mut VecDeque<Token>, mut data_type: DataType) -> DataType {
+    while tokens.front().map(|token| token.text()) == Some("*") {
+        tokens.pop_front();
+        data_type = DataType::Pointer(Box::new(data_type));
+    }
+    data_type
+}
+
+// Parses a type name, as used by casts, sizeof and _Alignof
+fn parse_type_name(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+) -> Result<DataType, ParseError> {
+    let specifiers = parse_specifiers(tokens, symbols)?;
+    if specifiers.storage_class.is_some() {
+        return error!("Storage class in type name");
+    }
+    Ok(parse_pointers(tokens, specifiers.data_type))
+}
+
+pub fn parse_specifiers(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+) -> Result<Specifiers, ParseError> {
+    let mut data_type: Option<DataType> = None;
+    let mut keywords: Vec<&str> = vec![];
+    let mut storage_class = None;
+    let mut constant = false;
+    loop {
+        let next_type = match tokens.front().map(|token| token.text()) {
+            Some(text @ ("typedef" | "static" | "extern")) => {
+                if storage_class.is_some() {
+                    return error!("Multiple storage classes in declaration");
+                }
+                storage_class = Some(match text {
+                    "typedef" => StorageClass::Typedef,
+                    "static" => StorageClass::Static,
+                    _ => StorageClass::Extern,
+                });
+                tokens.pop_front();
+                continue;
+            }
+            Some("const") => {
+                constant = true;
+                tokens.pop_front();
+                continue;
+            }
+            Some(text) if TYPE_KEYWORDS.contains(&text) => {
+                let keyword = TYPE_KEYWORDS.iter().find(|k| **k == text).unwrap();
+                keywords.push(keyword);
+                tokens.pop_front();
+                continue;
+            }
+            Some("enum") => parse_enum(tokens, symbols)?,
+            // Once a type has been given, an identifier is the declarator even
+            // if it also names a typedef (int T; shadowing typedef int T;)
+            Some(name)
+                if data_type.is_none()
+                    && keywords.is_empty()
+                    && symbols.typedef(name).is_some() =>
+            {
+                let underlying = symbols.typedef(name).unwrap().clone();
+                tokens.pop_front();
+                underlying
+            }
+            _ => break,
+        };
+        if let Some(old) = data_type {
+            return error!("Cannot combine {} with {}", old, next_type);
         }
-        let fname = tokens[1].text().to_string();
-        if fname != "main" {
-            return error!("Function name must be main");
+        data_type = Some(next_type);
+    }
+    if !keywords.is_empty() {
+        if let Some(old) = data_type {
+            return error!("Cannot combine {} with {}", old, keywords.join(" "));
         }
-        if tokens[2].text() != "(" {
-            return error!("Must have '(' after argument");
+        data_type = Some(basic_type(&keywords)?);
+    }
+    match data_type {
+        Some(data_type) => Ok(Specifiers {
+            data_type,
+            storage_class,
+            constant,
+        }),
+        None => error!("Missing type specifier"),
+    }
+}
+
+pub fn parse_enum(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+) -> Result<DataType, ParseError> {
+    expect(tokens, "enum")?;
+    let tag = match tokens.front() {
+        Some(token) if token.token_type() == &TokenType::IDENT => Some(expect_ident(tokens)?),
+        _ => None,
+    };
+    let data_type = DataType::Enum(tag.clone());
+    if tokens.front().map(|token| token.text()) != Some("{") {
+        return match tag {
+            Some(tag) => match symbols.lookup_tag(&tag) {
+                Some(data_type) => Ok(data_type.clone()),
+                None => error!("Use of undefined 'enum {}'", tag),
+            },
+            None => error!("Expected '{{' after 'enum'"),
+        };
+    }
+    tokens.pop_front();
+    if let Some(tag) = &tag {
+        symbols.declare_tag(tag, data_type.clone())?;
+    }
+    let mut value: i64 = 0;
+    while tokens.front().map(|token| token.text()) != Some("}") {
+        let name = expect_ident(tokens)?;
+        if tokens.front().map(|token| token.text()) == Some("=") {
+            tokens.pop_front();
+            let expression = parse_exp_nassign(tokens, symbols, 0)?;
+            value = match expression.evaluate() {
+                Some(x) => x,
+                None => return error!("Value of enumerator '{}' is not an integer constant", name),
+            };
         }
-        if tokens[3].text() != ")" {
-            return error!("Function cannot have arguments");
+        if value < i32::MIN as i64 || value > i32::MAX as i64 {
+            return error!(
+                "Value of enumerator '{}' ({}) does not fit in int",
+                name, value
+            );
         }
-        if tokens[4].text() != "{" {
-            return error!("Function cannot have arguments");
+        symbols.declare(&name, Symbol::EnumConstant(value))?;
+        value += 1;
+        match tokens.front().map(|token| token.text()) {
+            Some(",") => {
+                tokens.pop_front();
+            }
+            Some("}") => (),
+            _ => return error!("Expected ',' or '}}' after enumerator '{}'", name),
         }
-        tokens = tokens.split_off(5);
+    }
+    tokens.pop_front();
+    Ok(data_type)
+}
 
-        let mut fbody = vec![];
-        while tokens[0].text() != "}" {
-            match parse_statement(&mut tokens) {
-                Ok(statement) => fbody.push(statement),
-                Err(e) => {
-                    return Err(e);
+// Storage for a variable declared with the given storage class in the current scope
+fn variable_storage(
+    symbols: &mut SymbolTable,
+    name: &str,
+    storage_class: Option<StorageClass>,
+) -> Storage {
+    match storage_class {
+        Some(StorageClass::Static) if symbols.is_file_scope() => Storage::Static {
+            symbol: name.to_string(),
+            internal: true,
+        },
+        Some(StorageClass::Static) => Storage::Static {
+            symbol: symbols.static_symbol(name),
+            internal: true,
+        },
+        // A block scope extern refers to the file scope variable, if there is one
+        Some(StorageClass::Extern) => match symbols.lookup_global(name) {
+            Some(Symbol::Variable(variable)) if variable.storage != Storage::Automatic => {
+                variable.storage.clone()
+            }
+            _ => Storage::Extern(name.to_string()),
+        },
+        _ if symbols.is_file_scope() => Storage::Static {
+            symbol: name.to_string(),
+            internal: false,
+        },
+        _ => Storage::Automatic,
+    }
+}
+
+// Parses the declarators after the specifiers, up to and including the ';'
+pub fn parse_declaration(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+    specifiers: Specifiers,
+) -> Result<Vec<ast::Declaration>, ParseError> {
+    let mut declarations = vec![];
+    if tokens.front().map(|token| token.text()) == Some(";") {
+        tokens.pop_front();
+        return Ok(declarations);
+    }
+    loop {
+        let data_type = parse_pointers(tokens, specifiers.data_type.clone());
+        let name = expect_ident(tokens)?;
+        if tokens.front().map(|token| token.text()) == Some("(") {
+            // Block scope function prototype
+            if specifiers.storage_class.is_some() {
+                return error!("Invalid storage class for block scope function '{}'", name);
+            }
+            tokens.pop_front();
+            let params = parse_parameters(tokens, symbols)?;
+            let signature = FunctionType {
+                return_type: data_type,
+                params: params.map(|params| params.into_iter().map(|(_, t)| t).collect()),
+            };
+            symbols.declare_function(&name, signature, false)?;
+        } else if data_type.is_void() {
+            return error!("Variable '{}' declared void", name);
+        } else if specifiers.storage_class == Some(StorageClass::Typedef) {
+            symbols.declare(&name, Symbol::Typedef(data_type))?;
+        } else {
+            let mut variable = Variable {
+                data_type,
+                constant: specifiers.constant,
+                storage: variable_storage(symbols, &name, specifiers.storage_class),
+            };
+            // The variable is already in scope inside its own initializer
+            if symbols.is_file_scope() {
+                variable.storage = symbols.declare_global(&name, variable.clone())?;
+            } else {
+                symbols.declare(&name, Symbol::Variable(variable.clone()))?;
+            }
+            let initializer = if tokens.front().map(|token| token.text()) == Some("=") {
+                tokens.pop_front();
+                let expression = check_value(parse_exp(tokens, symbols)?)?;
+                Some(convert_implicit(expression, &variable.data_type)?)
+            } else {
+                None
+            };
+            if let Some(symbol) = variable.storage.symbol()
+                && let Some(initializer) = &initializer
+            {
+                if let Storage::Extern(_) = variable.storage
+                    && !symbols.is_file_scope()
+                {
+                    return error!("Block scope extern '{}' cannot have an initializer", name);
+                }
+                if !initializer.is_constant() {
+                    return error!("Initializer for '{}' is not a constant", name);
+                }
+                symbols.initialize(symbol)?;
+                // An initialized file scope extern is a definition
+                if let Storage::Extern(symbol) = variable.storage {
+                    variable.storage = Storage::Static {
+                        symbol,
+                        internal: false,
+                    };
+                    symbols.declare_global(&name, variable.clone())?;
                 }
             }
+            declarations.push(ast::Declaration {
+                name,
+                variable,
+                initializer,
+            });
+        }
+        match tokens.pop_front() {
+            Some(token) if token.text() == "," => (),
+            Some(token) if token.text() == ";" => break,
+            _ => return error!("Expected ',' or ';' after declarator"),
+        }
+    }
+    Ok(declarations)
+}
+
+// Name, if given, and type of each parameter
+type Parameters = Vec<(Option<String>, DataType)>;
+
+// Parses a parameter list after the '(' up to and including the ')'. Returns
+// None for an empty list, which leaves the parameters unspecified.
+pub fn parse_parameters(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+) -> Result<Option<Parameters>, ParseError> {
+    if tokens.front().map(|token| token.text()) == Some(")") {
+        tokens.pop_front();
+        return Ok(None);
+    }
+    if tokens.len() > 1 && tokens[0].text() == "void" && tokens[1].text() == ")" {
+        tokens.pop_front();
+        tokens.pop_front();
+        return Ok(Some(vec![]));
+    }
+    let mut params = vec![];
+    loop {
+        let specifiers = parse_specifiers(tokens, symbols)?;
+        if specifiers.storage_class.is_some() {
+            return error!("Invalid storage class for parameter {}", params.len() + 1);
         }
-        let func = ast::Function {
-            name: fname,
-            body: fbody,
+        let data_type = parse_pointers(tokens, specifiers.data_type);
+        if data_type.is_void() {
+            return error!("Parameter {} declared void", params.len() + 1);
+        }
+        let name = match tokens.front() {
+            Some(token) if token.token_type() == &TokenType::IDENT => Some(expect_ident(tokens)?),
+            _ => None,
         };
-        functions.push(func);
+        params.push((name, data_type));
+        match tokens.pop_front() {
+            Some(token) if token.text() == "," => (),
+            Some(token) if token.text() == ")" => break,
+            _ => return error!("Expected ',' or ')' after parameter"),
+        }
+    }
+    Ok(Some(params))
+}
+
+pub fn parse_function(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+    specifiers: Specifiers,
+) -> Result<Option<ast::Function>, ParseError> {
+    let return_type = parse_pointers(tokens, specifiers.data_type);
+    let fname = expect_ident(tokens)?;
+    expect(tokens, "(")?;
+    let params = parse_parameters(tokens, symbols)?;
+    match specifiers.storage_class {
+        Some(StorageClass::Typedef) => return error!("Function typedefs are not supported"),
+        _ if fname == "main" && return_type != DataType::Int => {
+            return error!("Function main must return int, not {}", return_type);
+        }
+        _ => (),
+    }
+    let signature = FunctionType {
+        return_type,
+        params: params
+            .as_ref()
+            .map(|params| params.iter().map(|(_, t)| t.clone()).collect()),
+    };
+    let is_static = specifiers.storage_class == Some(StorageClass::Static);
+    let (signature, internal) = symbols.declare_function(&fname, signature, is_static)?;
+    if tokens[0].text() == ";" {
+        // Prototype only
         tokens.pop_front();
+        return Ok(None);
+    }
+    expect(tokens, "{")?;
+    symbols.initialize(&fname)?;
+
+    symbols.push_scope();
+    let mut declarations = vec![];
+    for (i, (name, data_type)) in params.unwrap_or_default().into_iter().enumerate() {
+        let name = match name {
+            Some(name) => name,
+            None => return error!("Parameter {} of '{}' has no name", i + 1, fname),
+        };
+        let variable = Variable {
+            data_type,
+            constant: false,
+            storage: Storage::Automatic,
+        };
+        symbols.declare(&name, Symbol::Variable(variable.clone()))?;
+        declarations.push(ast::Declaration {
+            name,
+            variable,
+            initializer: None,
+        });
+    }
+    symbols.return_type = Some(signature.return_type.clone());
+    let mut fbody = vec![];
+    while tokens[0].text() != "}" {
+        match parse_statement(tokens, symbols) {
+            Ok(statement) => fbody.push(statement),
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+    symbols.return_type = None;
+    symbols.pop_scope();
+    tokens.pop_front();
+    Ok(Some(ast::Function {
+        name: fname,
+        signature,
+        params: declarations,
+        internal,
+        body: fbody,
+    }))
+}
+
+pub fn parse_program(mut tokens: VecDeque<Token>) -> Result<ast::Program, ParseError> {
+    let mut functions: Vec<ast::Function> = vec![];
+    let mut globals: Vec<ast::Declaration> = vec![];
+    let mut symbols = SymbolTable::new();
+    while !tokens.is_empty() {
+        if !is_declaration(&tokens, &symbols) {
+            return error!("Expected a declaration but found '{}'", tokens[0].text());
+        }
+        let specifiers = parse_specifiers(&mut tokens, &mut symbols)?;
+        let stars = tokens
+            .iter()
+            .take_while(|token| token.text() == "*")
+            .count();
+        if tokens.len() > stars + 1 && tokens[stars + 1].text() == "(" {
+            if let Some(func) = parse_function(&mut tokens, &mut symbols, specifiers)? {
+                functions.push(func);
+            }
+        } else {
+            let mut declarations = parse_declaration(&mut tokens, &mut symbols, specifiers)?;
+            globals.append(&mut declarations);
+        }
     }
-    Ok(ast::Program {
-        functions: functions,
-    })
+    Ok(ast::Program { globals, functions })
 }
 
-pub fn parse_statement(tokens: &mut VecDeque<Token>) -> Result<ast::Statement, ParseError> {
-    let mut statement: Option<ast::Statement> = None;
+pub fn parse_statement(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+) -> Result<ast::Statement, ParseError> {
+    if is_declaration(tokens, symbols) {
+        let specifiers = parse_specifiers(tokens, symbols)?;
+        return Ok(ast::Statement::DECLARE(parse_declaration(
+            tokens, symbols, specifiers,
+        )?));
+    }
 
-    if tokens[0].text() == "return" {
+    let statement = if tokens[0].text() == "return" {
         tokens.pop_front();
-        statement = Some(ast::Statement::RETURN(parse_exp(tokens)?));
+        let return_type = symbols.return_type.clone().unwrap();
+        if tokens[0].token_type() == &TokenType::SEMICOLON {
+            if !return_type.is_void() {
+                return error!("Non-void function should return a value");
+            }
+            ast::Statement::RETURN(None)
+        } else {
+            if return_type.is_void() {
+                return error!("Void function should not return a value");
+            }
+            let expression = check_value(parse_exp(tokens, symbols)?)?;
+            ast::Statement::RETURN(Some(convert_implicit(expression, &return_type)?))
+        }
     } else {
-        println!("{}", tokens[0].text());
-    }
+        ast::Statement::EXPRESSION(parse_exp(tokens, symbols)?)
+    };
 
     if tokens[0].token_type() != &TokenType::SEMICOLON {
         return error!("Statement must end semicolon");
     }
     *tokens = tokens.split_off(1);
-    match statement {
-        Some(s) => Ok(s),
-        None => error!("Something went wrong"),
-    }
+    Ok(statement)
 }
 
-pub fn parse_exp(tokens: &mut VecDeque<Token>) -> Result<Box<dyn ast::Expression>, ParseError> {
+pub fn parse_exp(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+) -> Result<Box<dyn ast::Expression>, ParseError> {
     if tokens.len() > 1 && tokens[1].text() == "=" {
         //assignment
         if tokens[0].token_type() != &TokenType::IDENT {
             return error!("");
         }
         let var_name = tokens.pop_front().unwrap().text().to_string();
+        let variable = match symbols.lookup(&var_name) {
+            Some(Symbol::Variable(variable)) if variable.constant => {
+                return error!("Cannot assign to const variable '{}'", var_name);
+            }
+            Some(Symbol::Variable(variable)) => variable.clone(),
+            Some(Symbol::Function { .. }) => {
+                return error!("Cannot assign to function '{}'", var_name);
+            }
+            Some(Symbol::EnumConstant(_)) => {
+                return error!("Cannot assign to enumerator '{}'", var_name);
+            }
+            Some(Symbol::Typedef(data_type)) => {
+                return error!("Cannot assign to type '{}' ({})", var_name, data_type);
+            }
+            None => return error!("Use of undeclared identifier '{}'", var_name),
+        };
         tokens.pop_front(); //pop "="
-        let exp = parse_exp_nassign(tokens, 0)?;
+        let exp = check_value(parse_exp(tokens, symbols)?)?;
         let ast = ast::Assign {
-            var_name: var_name,
-            expression: exp,
+            var_name,
+            expression: convert_implicit(exp, &variable.data_type)?,
+            data_type: variable.data_type,
+            storage: variable.storage,
         };
         Ok(Box::new(ast))
     } else {
         //operation
-        parse_exp_nassign(tokens, 0)
+        let expression = parse_exp_nassign(tokens, symbols, 0)?;
+        if tokens.front().map(|token| token.text()) != Some("=") {
+            return Ok(expression);
+        }
+        tokens.pop_front(); //pop "="
+        // Assignments to variables were handled above, so only a
+        // dereference is left that can be assigned to
+        let data_type = expression.data_type();
+        let pointer = match expression.into_pointer() {
+            Some(pointer) => pointer,
+            None => return error!("Expression is not assignable"),
+        };
+        let exp = check_value(parse_exp(tokens, symbols)?)?;
+        Ok(Box::new(ast::DereferenceAssign {
+            pointer,
+            expression: convert_implicit(exp, &data_type)?,
+            data_type,
+        }))
     }
 }
 
 pub fn parse_exp_nassign(
     tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
     depth: i64,
 ) -> Result<Box<dyn ast::Expression>, ParseError> {
     let mut term = match depth + 1 {
-        _ if depth + 1 < *OP_LEN => parse_exp_nassign(tokens, depth + 1)?,
-        _ => parse_factor(tokens)?,
+        _ if depth + 1 < *OP_LEN => parse_exp_nassign(tokens, symbols, depth + 1)?,
+        _ => parse_factor(tokens, symbols)?,
     };
     while tokens[0].token_type() == &TokenType::OPER {
         if !OP_ORDER[depth as usize].contains(&tokens[0].text()) {
             break;
         }
         let oper = tokens.pop_front().unwrap();
-        let next_term = parse_exp_nassign(tokens, depth)?;
+        // Parse only the tighter binding operators so that the loop builds a
+        // left associative tree (a - b - c is (a - b) - c)
+        let next_term = match depth + 1 {
+            _ if depth + 1 < *OP_LEN => parse_exp_nassign(tokens, symbols, depth + 1)?,
+            _ => parse_factor(tokens, symbols)?,
+        };
+        let oper = match ast::BinOpType::from_str(oper.text()) {
+            Ok(x) => x,
+            Err(_) => {
+                return error!("Invalid binary operator: ");
+            }
+        };
+        let (mut value_a, mut value_b) = (check_value(term)?, check_value(next_term)?);
+        // The operands are converted, except for the logical operators
+        // which only test them against zero
+        if !matches!(oper, ast::BinOpType::LogicalOr | ast::BinOpType::LogicalAnd) {
+            let (type_a, type_b) = operand_types(&*value_a, &*value_b, oper)?;
+            value_a = ast::convert(value_a, &type_a);
+            value_b = ast::convert(value_b, &type_b);
+        }
         term = Box::new(ast::BinOp {
-            value_a: term,
-            value_b: next_term,
-            oper: match ast::BinOpType::from_str(oper.text()) {
-                Ok(x) => x,
-                Err(_) => {
-                    return error!("Invalid binary operator: ");
-                }
-            },
+            value_a,
+            value_b,
+            oper,
         })
     }
     Ok(term)
 }
 
-pub fn parse_factor(tokens: &mut VecDeque<Token>) -> Result<Box<dyn ast::Factor>, ParseError> {
+pub fn parse_factor(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+) -> Result<Box<dyn ast::Expression>, ParseError> {
     let next_token = tokens.pop_front().unwrap();
     match *next_token.token_type() {
-        TokenType::PARENTH => todo!(),
-        TokenType::IDENT => todo!(),
+        TokenType::PARENTH if next_token.text() == "(" => {
+            if is_type_name(tokens.front(), symbols) {
+                let data_type = parse_type_name(tokens, symbols)?;
+                expect(tokens, ")")?;
+                let value = check_value(parse_factor(tokens, symbols)?)?;
+                return cast(value, data_type);
+            }
+            let expression = parse_exp(tokens, symbols)?;
+            expect(tokens, ")")?;
+            Ok(expression)
+        }
+        TokenType::IDENT if next_token.text() == "sizeof" => {
+            let data_type = if tokens.front().map(|token| token.text()) == Some("(")
+                && is_type_name(tokens.get(1), symbols)
+            {
+                tokens.pop_front();
+                let data_type = parse_type_name(tokens, symbols)?;
+                expect(tokens, ")")?;
+                data_type
+            } else {
+                // The operand is only looked at for its type, never evaluated
+                parse_factor(tokens, symbols)?.data_type()
+            };
+            if data_type.is_void() {
+                return error!("Invalid application of 'sizeof' to void");
+            }
+            Ok(size_constant(data_type.size()))
+        }
+        TokenType::IDENT if next_token.text() == "_Alignof" => {
+            expect(tokens, "(")?;
+            if !is_type_name(tokens.front(), symbols) {
+                return error!("_Alignof needs a type name");
+            }
+            let data_type = parse_type_name(tokens, symbols)?;
+            expect(tokens, ")")?;
+            if data_type.is_void() {
+                return error!("Invalid application of '_Alignof' to void");
+            }
+            Ok(size_constant(data_type.alignment()))
+        }
+        TokenType::IDENT => match symbols.lookup(next_token.text()) {
+            Some(Symbol::Variable(variable)) => Ok(Box::new(ast::Var {
+                name: next_token.text().to_string(),
+                data_type: variable.data_type.clone(),
+                storage: variable.storage.clone(),
+            })),
+            Some(Symbol::EnumConstant(value)) => Ok(Box::new(ast::Const {
+                value: *value as u64,
+            })),
+            Some(Symbol::Typedef(data_type)) => error!(
+                "Unexpected type name '{}' ({}) in expression",
+                next_token.text(),
+                data_type
+            ),
+            Some(Symbol::Function { signature, .. }) => {
+                let signature = signature.clone();
+                parse_call(tokens, symbols, next_token.text(), signature)
+            }
+            None => error!("Use of undeclared identifier '{}'", next_token.text()),
+        },
         TokenType::INT => Ok(Box::new(ast::Const {
             value: match next_token.text().parse() {
                 Ok(x) => x,
@@ -173,13 +766,220 @@ pub fn parse_factor(tokens: &mut VecDeque<Token>) -> Result<Box<dyn ast::Factor>
                 }
             },
         })),
-        TokenType::OPER => Ok(Box::new(ast::UnOp {
-            value: parse_factor(tokens)?,
-            oper: match ast::UnOpType::from_str(next_token.text()) {
+        TokenType::FLOAT => {
+            let text = next_token.text();
+            let value = match text.strip_suffix(['f', 'F']) {
+                Some(digits) => digits
+                    .parse::<f32>()
+                    .map(|value| (value as f64, DataType::Float)),
+                None => text.parse::<f64>().map(|value| (value, DataType::Double)),
+            };
+            match value {
+                Ok((value, data_type)) => Ok(Box::new(ast::FloatConst { value, data_type })),
+                Err(_) => error!("Invalid floating point constant '{}'", text),
+            }
+        }
+        TokenType::OPER if next_token.text() == "*" => {
+            let pointer = check_value(parse_factor(tokens, symbols)?)?;
+            let data_type = match pointer.data_type() {
+                DataType::Pointer(data_type) if data_type.is_void() => {
+                    return error!("Dereferencing a void pointer");
+                }
+                DataType::Pointer(data_type) => *data_type,
+                data_type => return error!("Invalid operand to unary '*': {}", data_type),
+            };
+            Ok(Box::new(ast::Dereference { pointer, data_type }))
+        }
+        TokenType::OPER => {
+            let oper = match ast::UnOpType::from_str(next_token.text()) {
                 Ok(x) => x,
                 Err(_) => return error!("Invalid unary operator: {}", next_token.text()),
-            },
-        })),
+            };
+            let value = check_value(parse_factor(tokens, symbols)?)?;
+            let data_type = value.data_type();
+            match oper {
+                ast::UnOpType::Complement if data_type.is_float() || data_type.is_pointer() => {
+                    return error!("Invalid operand to '~': {}", data_type);
+                }
+                ast::UnOpType::Negate if data_type.is_pointer() => {
+                    return error!("Invalid operand to '-': {}", data_type);
+                }
+                _ => (),
+            }
+            Ok(Box::new(ast::UnOp { value, oper }))
+        }
         _ => error!("Wrong token in factor"),
     }
 }
+
+// Value of sizeof or _Alignof, which has type size_t
+fn size_constant(size: u64) -> Box<dyn ast::Expression> {
+    ast::convert(
+        Box::new(ast::Const { value: size }),
+        &DataType::UnsignedLong,
+    )
+}
+
+// Whether the expression is an integer constant expression equal to 0
+fn is_null_constant(expression: &dyn ast::Expression) -> bool {
+    let data_type = expression.data_type();
+    !data_type.is_pointer() && !data_type.is_float() && expression.evaluate() == Some(0)
+}
+
+// The types the operands of a binary operator are converted to. Both get a
+// common type, except that the operands of a shift are only promoted. Pointers
+// can only be compared, with a pointer of the same type or a null pointer
+// constant.
+fn operand_types(
+    a: &dyn ast::Expression,
+    b: &dyn ast::Expression,
+    oper: ast::BinOpType,
+) -> Result<(DataType, DataType), ParseError> {
+    let (type_a, type_b) = (a.data_type(), b.data_type());
+    let integers =
+        !type_a.is_pointer() && !type_b.is_pointer() && !type_a.is_float() && !type_b.is_float();
+    let valid = match oper {
+        ast::BinOpType::Remainder
+        | ast::BinOpType::BitAnd
+        | ast::BinOpType::BitOr
+        | ast::BinOpType::BitXor
+        | ast::BinOpType::ShiftLeft
+        | ast::BinOpType::ShiftRight => integers,
+        ast::BinOpType::Add
+        | ast::BinOpType::Subtract
+        | ast::BinOpType::Multiply
+        | ast::BinOpType::Divide => !type_a.is_pointer() && !type_b.is_pointer(),
+        _ => true,
+    };
+    if !valid {
+        return error!(
+            "Invalid operands to '{}': {} and {}",
+            Into::<&str>::into(oper),
+            type_a,
+            type_b
+        );
+    }
+    if let ast::BinOpType::ShiftLeft | ast::BinOpType::ShiftRight = oper {
+        return Ok((type_a.promote(), type_b.promote()));
+    }
+    if !type_a.is_pointer() && !type_b.is_pointer() {
+        let common = DataType::common(&type_a, &type_b);
+        return Ok((common.clone(), common));
+    }
+    if type_a == type_b || is_null_constant(b) {
+        Ok((type_a.clone(), type_a))
+    } else if is_null_constant(a) {
+        Ok((type_b.clone(), type_b))
+    } else {
+        error!("Comparison between {} and {}", type_a, type_b)
+    }
+}
+
+// Conversion as if by assignment, which also applies to initializers,
+// arguments and return values. Pointers only convert implicitly to and from
+// void pointers, and from null pointer constants.
+fn convert_implicit(
+    expression: Box<dyn ast::Expression>,
+    data_type: &DataType,
+) -> Result<Box<dyn ast::Expression>, ParseError> {
+    let from = expression.data_type();
+    let allowed = match (&from, data_type) {
+        (DataType::Pointer(a), DataType::Pointer(b)) => a == b || a.is_void() || b.is_void(),
+        (DataType::Pointer(_), _) | (_, DataType::Pointer(_)) => is_null_constant(&*expression),
+        _ => true,
+    };
+    if !allowed {
+        return error!("Cannot convert {} to {} without a cast", from, data_type);
+    }
+    Ok(ast::convert(expression, data_type))
+}
+
+// Explicit cast. Any scalar can be cast to any other, except between
+// pointers and floating point values.
+fn cast(
+    value: Box<dyn ast::Expression>,
+    data_type: DataType,
+) -> Result<Box<dyn ast::Expression>, ParseError> {
+    let from = value.data_type();
+    if (from.is_pointer() && data_type.is_float()) || (from.is_float() && data_type.is_pointer()) {
+        return error!("Cannot cast {} to {}", from, data_type);
+    }
+    if data_type.is_void() {
+        return Ok(Box::new(ast::Convert { value, data_type }));
+    }
+    Ok(ast::convert(value, &data_type))
+}
+
+// Rejects void expressions where a value is needed
+fn check_value<T: ast::Expression + ?Sized>(expression: Box<T>) -> Result<Box<T>, ParseError> {
+    if expression.data_type().is_void() {
+        return error!("Invalid use of void expression");
+    }
+    Ok(expression)
+}
+
+pub fn parse_call(
+    tokens: &mut VecDeque<Token>,
+    symbols: &mut SymbolTable,
+    name: &str,
+    signature: FunctionType,
+) -> Result<Box<dyn ast::Expression>, ParseError> {
+    if tokens.front().map(|token| token.text()) != Some("(") {
+        return error!("Function '{}' can only be called", name);
+    }
+    tokens.pop_front();
+    let mut args = vec![];
+    while tokens.front().map(|token| token.text()) != Some(")") {
+        if !args.is_empty() {
+            expect(tokens, ",")?;
+        }
+        let arg = match parse_exp(tokens, symbols)? {
+            arg if arg.data_type().is_void() => {
+                return error!(
+                    "Passing void expression as argument {} of '{}'",
+                    args.len() + 1,
+                    name
+                );
+            }
+            arg => arg,
+        };
+        args.push(arg);
+    }
+    tokens.pop_front();
+    let args = match &signature.params {
+        Some(params) if params.len() != args.len() => {
+            return error!(
+                "Too {} arguments to function '{}': expected {}, got {}",
+                if args.len() < params.len() {
+                    "few"
+                } else {
+                    "many"
+                },
+                name,
+                params.len(),
+                args.len()
+            );
+        }
+        Some(params) => args
+            .into_iter()
+            .zip(params.iter())
+            .map(|(arg, param)| convert_implicit(arg, param))
+            .collect::<Result<_, _>>()?,
+        // Without a prototype only the default argument promotions apply
+        None => args
+            .into_iter()
+            .map(|arg| {
+                let promoted = match arg.data_type() {
+                    DataType::Float => DataType::Double,
+                    data_type => data_type.promote(),
+                };
+                ast::convert(arg, &promoted)
+            })
+            .collect(),
+    };
+    Ok(Box::new(ast::Call {
+        name: name.to_string(),
+        args,
+        return_type: signature.return_type,
+    }))
+}
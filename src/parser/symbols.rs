@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ParseError;
+use super::types::{DataType, FunctionType};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Storage {
+    Automatic,                                 // Lives in a TAC variable
+    Static { symbol: String, internal: bool }, // Defined in this file
+    Extern(String),                            // Defined elsewhere, unless a definition follows
+}
+impl Storage {
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            Storage::Automatic => None,
+            Storage::Static { symbol, .. } | Storage::Extern(symbol) => Some(symbol),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub data_type: DataType,
+    pub constant: bool,
+    pub storage: Storage,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    Typedef(DataType), // Typedef names resolve straight to their underlying type
+    Variable(Variable),
+    EnumConstant(i64),
+    Function {
+        signature: FunctionType,
+        internal: bool,
+    },
+}
+
+#[derive(Debug)]
+struct Frame {
+    symbols: HashMap<String, Symbol>,
+    tags: HashMap<String, DataType>, // enum tags live in their own namespace
+}
+
+#[derive(Debug)]
+pub struct SymbolTable {
+    frames: Vec<Frame>,
+    initialized: HashSet<String>, // Static symbols and functions that have been defined
+    static_counter: u64,
+    pub return_type: Option<DataType>, // Of the function whose body is being parsed
+}
+impl SymbolTable {
+    pub fn new() -> Self {
+        let mut table = Self {
+            frames: vec![],
+            initialized: HashSet::new(),
+            static_counter: 0,
+            return_type: None,
+        };
+        table.push_scope();
+        table
+    }
+    pub fn is_file_scope(&self) -> bool {
+        self.frames.len() == 1
+    }
+    // Symbol for a block scope static, unique across the whole program
+    pub fn static_symbol(&mut self, name: &str) -> String {
+        self.static_counter += 1;
+        format!("{}.{}", name, self.static_counter)
+    }
+    pub fn initialize(&mut self, symbol: &str) -> Result<(), ParseError> {
+        if !self.initialized.insert(symbol.to_string()) {
+            return Err(ParseError::new(format!("Redefinition of '{}'", symbol)));
+        }
+        Ok(())
+    }
+    // Declares a file scope variable, merging it with earlier declarations of
+    // the same name. Returns the storage the name ends up with.
+    pub fn declare_global(
+        &mut self,
+        name: &str,
+        variable: Variable,
+    ) -> Result<Storage, ParseError> {
+        let frame = self.frames.first_mut().unwrap();
+        let old = match frame.symbols.get(name) {
+            Some(Symbol::Variable(old)) => old.clone(),
+            Some(_) => return Err(ParseError::new(format!("Redeclaration of '{}'", name))),
+            None => {
+                let storage = variable.storage.clone();
+                frame
+                    .symbols
+                    .insert(name.to_string(), Symbol::Variable(variable));
+                return Ok(storage);
+            }
+        };
+        if old.data_type != variable.data_type || old.constant != variable.constant {
+            return Err(ParseError::new(format!(
+                "Conflicting types for '{}': {} and {}",
+                name, old.data_type, variable.data_type
+            )));
+        }
+        let storage = match (&old.storage, variable.storage) {
+            (
+                Storage::Static {
+                    internal: false, ..
+                },
+                Storage::Static { internal: true, .. },
+            )
+            | (Storage::Extern(_), Storage::Static { internal: true, .. }) => {
+                return Err(ParseError::new(format!(
+                    "Static declaration of '{}' follows non-static declaration",
+                    name
+                )));
+            }
+            (
+                Storage::Static { internal: true, .. },
+                Storage::Static {
+                    internal: false, ..
+                },
+            ) => {
+                return Err(ParseError::new(format!(
+                    "Non-static declaration of '{}' follows static declaration",
+                    name
+                )));
+            }
+            // extern keeps whatever linkage was given before it
+            (old, Storage::Extern(_)) => old.clone(),
+            (_, new) => new,
+        };
+        frame.symbols.insert(
+            name.to_string(),
+            Symbol::Variable(Variable {
+                storage: storage.clone(),
+                ..old
+            }),
+        );
+        Ok(storage)
+    }
+    pub fn push_scope(&mut self) {
+        self.frames.push(Frame {
+            symbols: HashMap::new(),
+            tags: HashMap::new(),
+        });
+    }
+    pub fn pop_scope(&mut self) {
+        self.frames.pop();
+    }
+    pub fn declare(&mut self, name: &str, symbol: Symbol) -> Result<(), ParseError> {
+        let frame = self.frames.last_mut().unwrap();
+        match frame.symbols.get(name) {
+            // C11 allows repeating a typedef as long as the type is the same
+            Some(Symbol::Typedef(old)) if symbol == Symbol::Typedef(old.clone()) => Ok(()),
+            Some(Symbol::Typedef(old)) => Err(ParseError::new(format!(
+                "Conflicting types for typedef '{}': {} and {}",
+                name,
+                old,
+                match symbol {
+                    Symbol::Typedef(new) => new.to_string(),
+                    _ => String::from("non-type"),
+                }
+            ))),
+            Some(_) => Err(ParseError::new(format!("Redeclaration of '{}'", name))),
+            None => {
+                frame.symbols.insert(name.to_string(), symbol);
+                Ok(())
+            }
+        }
+    }
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.symbols.get(name))
+    }
+    // Declares a function at file scope, merging it with earlier declarations,
+    // and makes it visible in the current scope. Returns the merged signature
+    // and whether the function has internal linkage.
+    pub fn declare_function(
+        &mut self,
+        name: &str,
+        signature: FunctionType,
+        is_static: bool,
+    ) -> Result<(FunctionType, bool), ParseError> {
+        let (signature, internal) = match self.lookup_global(name) {
+            Some(Symbol::Function {
+                signature: old,
+                internal,
+            }) => {
+                if !old.compatible(&signature) {
+                    return Err(ParseError::new(format!(
+                        "Conflicting types for '{}': {} and {}",
+                        name, old, signature
+                    )));
+                }
+                if is_static && !internal {
+                    return Err(ParseError::new(format!(
+                        "Static declaration of '{}' follows non-static declaration",
+                        name
+                    )));
+                }
+                // Keep the parameter list if only one declaration had one
+                let signature = match signature.params {
+                    Some(_) => signature,
+                    None => old.clone(),
+                };
+                (signature, *internal)
+            }
+            Some(_) => {
+                return Err(ParseError::new(format!(
+                    "'{}' redeclared as a different kind of symbol",
+                    name
+                )));
+            }
+            None => (signature, is_static),
+        };
+        let symbol = Symbol::Function {
+            signature: signature.clone(),
+            internal,
+        };
+        self.frames
+            .first_mut()
+            .unwrap()
+            .symbols
+            .insert(name.to_string(), symbol.clone());
+        if !self.is_file_scope() {
+            let frame = self.frames.last_mut().unwrap();
+            if let Some(Symbol::Variable(_) | Symbol::Typedef(_) | Symbol::EnumConstant(_)) =
+                frame.symbols.get(name)
+            {
+                return Err(ParseError::new(format!("Redeclaration of '{}'", name)));
+            }
+            frame.symbols.insert(name.to_string(), symbol);
+        }
+        Ok((signature, internal))
+    }
+    pub fn lookup_global(&self, name: &str) -> Option<&Symbol> {
+        self.frames.first().unwrap().symbols.get(name)
+    }
+    pub fn typedef(&self, name: &str) -> Option<&DataType> {
+        match self.lookup(name) {
+            Some(Symbol::Typedef(data_type)) => Some(data_type),
+            _ => None,
+        }
+    }
+    pub fn declare_tag(&mut self, tag: &str, data_type: DataType) -> Result<(), ParseError> {
+        let frame = self.frames.last_mut().unwrap();
+        if frame.tags.contains_key(tag) {
+            return Err(ParseError::new(format!("Redefinition of 'enum {}'", tag)));
+        }
+        frame.tags.insert(tag.to_string(), data_type);
+        Ok(())
+    }
+    pub fn lookup_tag(&self, tag: &str) -> Option<&DataType> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.tags.get(tag))
+    }
+}
@@ -0,0 +1,164 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataType {
+    Void,
+    Char, // Plain char is signed, as in the System V ABI
+    UnsignedChar,
+    Short,
+    UnsignedShort,
+    Int,
+    UnsignedInt,
+    Long,
+    UnsignedLong,
+    Float,
+    Double,
+    Enum(Option<String>), // Tag, if the enum was named
+    Pointer(Box<DataType>),
+}
+impl DataType {
+    pub fn size(&self) -> u64 {
+        match self {
+            DataType::Void => 0,
+            DataType::Char | DataType::UnsignedChar => 1,
+            DataType::Short | DataType::UnsignedShort => 2,
+            DataType::Int | DataType::UnsignedInt | DataType::Float | DataType::Enum(_) => 4,
+            DataType::Long | DataType::UnsignedLong | DataType::Double | DataType::Pointer(_) => 8,
+        }
+    }
+    pub fn is_signed(&self) -> bool {
+        !matches!(
+            self,
+            DataType::UnsignedChar
+                | DataType::UnsignedShort
+                | DataType::UnsignedInt
+                | DataType::UnsignedLong
+                | DataType::Pointer(_)
+        )
+    }
+    pub fn is_void(&self) -> bool {
+        *self == DataType::Void
+    }
+    pub fn is_float(&self) -> bool {
+        matches!(self, DataType::Float | DataType::Double)
+    }
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, DataType::Pointer(_))
+    }
+    // Every type is aligned to its own size, as in the System V ABI
+    pub fn alignment(&self) -> u64 {
+        self.size()
+    }
+    // Integer promotions: anything narrower than int is computed as int
+    pub fn promote(&self) -> DataType {
+        match self {
+            DataType::Char
+            | DataType::UnsignedChar
+            | DataType::Short
+            | DataType::UnsignedShort
+            | DataType::Enum(_) => DataType::Int,
+            other => other.clone(),
+        }
+    }
+    // Usual arithmetic conversions for the operands of a binary operator
+    pub fn common(a: &DataType, b: &DataType) -> DataType {
+        let (a, b) = (a.promote(), b.promote());
+        if a == b {
+            return a;
+        }
+        if a == DataType::Double || b == DataType::Double {
+            return DataType::Double;
+        }
+        if a == DataType::Float || b == DataType::Float {
+            return DataType::Float;
+        }
+        if a == DataType::UnsignedLong || b == DataType::UnsignedLong {
+            return DataType::UnsignedLong;
+        }
+        // long can hold every unsigned int, so it wins over it
+        if a == DataType::Long || b == DataType::Long {
+            return DataType::Long;
+        }
+        DataType::UnsignedInt
+    }
+    // Truncates a value to this type, extending it back to 64 bits
+    pub fn truncate(&self, value: i64) -> i64 {
+        match (self.size(), self.is_signed()) {
+            (1, true) => value as i8 as i64,
+            (1, false) => value as u8 as i64,
+            (2, true) => value as i16 as i64,
+            (2, false) => value as u16 as i64,
+            (4, true) => value as i32 as i64,
+            (4, false) => value as u32 as i64,
+            _ => value,
+        }
+    }
+    // Rounds a value to the precision of this floating point type
+    pub fn round(&self, value: f64) -> f64 {
+        match self {
+            DataType::Float => value as f32 as f64,
+            _ => value,
+        }
+    }
+    // Bit pattern of a value of this floating point type, as stored in memory
+    pub fn float_bits(&self, value: f64) -> u64 {
+        match self {
+            DataType::Float => (value as f32).to_bits() as u64,
+            _ => value.to_bits(),
+        }
+    }
+}
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataType::Void => write!(f, "void"),
+            DataType::Char => write!(f, "char"),
+            DataType::UnsignedChar => write!(f, "unsigned char"),
+            DataType::Short => write!(f, "short"),
+            DataType::UnsignedShort => write!(f, "unsigned short"),
+            DataType::Int => write!(f, "int"),
+            DataType::UnsignedInt => write!(f, "unsigned int"),
+            DataType::Long => write!(f, "long"),
+            DataType::UnsignedLong => write!(f, "unsigned long"),
+            DataType::Float => write!(f, "float"),
+            DataType::Double => write!(f, "double"),
+            DataType::Enum(Some(tag)) => write!(f, "enum {}", tag),
+            DataType::Enum(None) => write!(f, "enum <anonymous>"),
+            DataType::Pointer(to) if to.is_pointer() => write!(f, "{}*", to),
+            DataType::Pointer(to) => write!(f, "{} *", to),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType {
+    pub return_type: DataType,
+    pub params: Option<Vec<DataType>>, // None for an old style f() declaration
+}
+impl FunctionType {
+    // Two declarations of the same function agree if they only differ in
+    // whether the parameters were given
+    pub fn compatible(&self, other: &FunctionType) -> bool {
+        self.return_type == other.return_type
+            && match (&self.params, &other.params) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+}
+impl std::fmt::Display for FunctionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (", self.return_type)?;
+        match &self.params {
+            Some(params) if params.is_empty() => write!(f, "void")?,
+            Some(params) => {
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+            }
+            None => (),
+        }
+        write!(f, ")")
+    }
+}
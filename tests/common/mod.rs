@@ -0,0 +1,70 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+// Runs the compiler with some arguments
+pub fn compiler(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// The exit status the interpreter gives for a program at an optimization
+// level, which is the low byte of what main returns
+#[allow(dead_code)] // Not every test file interprets programs
+pub fn interpret(path: &str, level: &str) -> u8 {
+    let output = compiler(&[path, level, "--interpret"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{} at {}: {}",
+        path,
+        level,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let status = stdout
+        .lines()
+        .rev()
+        .find_map(|line| line.strip_prefix("exit status: "))
+        .unwrap();
+    status.parse().unwrap()
+}
+
+// The exit status of a program compiled at an optimization level, assembled
+// with gcc in a directory of its own rather than in out/
+#[allow(dead_code)] // Not every test file runs programs
+pub fn run(path: &str, level: &str) -> u8 {
+    let output = compiler(&[path, level, "-S"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{} at {}: {}",
+        path,
+        level,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // The assembly comes after the TAC, starting with the names it gives
+    // the symbols it references, if there are any
+    let start = stdout
+        .find(".set .Lsym.")
+        .unwrap_or_else(|| stdout.find(".intel_syntax").unwrap());
+    let name = Path::new(path).file_stem().unwrap().to_str().unwrap();
+    let stem = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}{}", name, level));
+    let assembly = stem.with_extension("s");
+    std::fs::write(&assembly, &stdout[start..]).unwrap();
+    let gcc = Command::new("gcc")
+        .arg(&assembly)
+        .arg("-o")
+        .arg(&stem)
+        .output()
+        .unwrap();
+    assert!(
+        gcc.status.success(),
+        "{} at {}: {}",
+        path,
+        level,
+        String::from_utf8_lossy(&gcc.stderr)
+    );
+    let status = Command::new(&stem).status().unwrap();
+    status.code().unwrap() as u8
+}
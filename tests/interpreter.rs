@@ -0,0 +1,114 @@
+mod common;
+
+// These run without gcc, so they check what main returns at every level
+
+// Recursion, mutual recursion, a negative result and a float argument
+#[test]
+fn calls() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/calls.tac", level), 132);
+    }
+}
+
+// A load reads back only as many bytes as it asks for, zero extended
+#[test]
+fn loads_and_stores() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/memory.tac", level), 4);
+    }
+}
+
+// Signed division rounds towards zero, unsigned division sees a huge number
+#[test]
+fn division() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/division.tac", level), 37);
+    }
+}
+
+// Pointers from malloc passed around, a static function, and division of a
+// negative int
+#[test]
+fn c_program() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/memory.c", level), 27);
+    }
+}
+
+// Results keep the low bytes for the type of their variable, and moves and
+// calls convert between types
+#[test]
+fn types() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/types.tac", level), 125);
+    }
+}
+
+// A result truncated by a narrow variable stays truncated when it is
+// returned right away, so that call is not turned into a jump
+#[test]
+fn narrow_tail_call() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(
+            common::interpret("tests/programs/narrow_tail.tac", level),
+            0
+        );
+    }
+}
+
+// Every call has stack slots of its own, which stay put while a callee
+// reads and writes them through their address, even once small callees are
+// inlined and calls whose result is returned right away are looked at
+#[test]
+fn stack_slots() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/slots.tac", level), 36);
+    }
+}
+
+// A computation in the inner of two loops that gives the same value on
+// every iteration gives the same result once it is moved out of both
+#[test]
+fn nested_loop_invariant() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(
+            common::interpret("tests/programs/nested_loop.tac", level),
+            224
+        );
+    }
+    // At -O2 the multiplication comes before the loops start
+    let output = common::compiler(&["tests/programs/nested_loop.tac", "-O2", "--interpret"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let sum = &stdout[stdout.find("Function sum").unwrap()..];
+    assert!(
+        sum.find("multiply").unwrap() < sum.find("L0:").unwrap(),
+        "{}",
+        sum
+    );
+}
+
+#[test]
+fn induction_variable() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/induction.tac", level), 28);
+    }
+    // At -O2 the product is carried along instead of recomputed
+    let output = common::compiler(&["tests/programs/induction.tac", "-O2", "--interpret"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("multiply"), "{}", stdout);
+}
+
+// Without the tail pass every call keeps its frame
+#[test]
+fn deep_recursion() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(
+            common::interpret("tests/programs/deep_recursion.tac", level),
+            32
+        );
+        assert_eq!(
+            common::interpret("tests/programs/mutual_recursion.tac", level),
+            1
+        );
+    }
+}
@@ -0,0 +1,57 @@
+mod common;
+
+// The functions in the printed program, in order
+fn functions(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("Function "))
+        .collect()
+}
+
+#[test]
+fn static_calls() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(
+            common::interpret("tests/programs/static_calls.tac", level),
+            25
+        );
+    }
+}
+
+// Every call passes scale the same factor, which ipcp writes to the
+// parameter on entry
+#[test]
+fn constant_argument() {
+    let output = common::compiler(&[
+        "tests/programs/static_calls.tac",
+        "-O2",
+        "--print-after=ipcp",
+        "--interpret",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let after = &stdout[stdout.find("After ipcp:").unwrap()..];
+    assert!(
+        after.contains("Function scale(%0, %1) static:\nmove $3, %1\n"),
+        "{}",
+        after
+    );
+}
+
+// Other files may call exported, but nothing calls forgotten
+#[test]
+fn unused_functions() {
+    let output = common::compiler(&["tests/programs/static_calls.tac", "-O1", "--interpret"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        functions(&stdout),
+        [
+            "main():",
+            "scale(%0, %1) static:",
+            "exported():",
+            "helper() static:"
+        ]
+    );
+    let output = common::compiler(&["tests/programs/static_calls.tac", "-O0", "--interpret"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(functions(&stdout).contains(&"forgotten() static:"));
+}
@@ -0,0 +1,27 @@
+mod common;
+
+// Variables the loop reads go round the back edge, so they are live on
+// entry to and exit from every block of the loop, and only the result is
+// live once it is left
+#[test]
+fn live_across_loop() {
+    let output = common::compiler(&[
+        "tests/programs/loop.tac",
+        "-O0",
+        "--interpret",
+        "--liveness",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected = "Liveness of main:
+block 2: in {}, out {%0, %1}
+block 3: in {%0, %1}, out {%0, %1}
+block 4: in {%0, %1}, out {%0, %1}
+block 5: in {%0}, out {}
+";
+    assert!(stdout.contains(expected), "{}", stdout);
+}
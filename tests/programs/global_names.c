@@ -0,0 +1,9 @@
+int lt = 4;
+long rax = 10;
+static char mod = 3;
+
+int main() {
+    rax = rax + lt;
+    mod = mod * 2;
+    return rax + mod;
+}
@@ -0,0 +1,14 @@
+void *malloc(unsigned long size);
+void free(void *p);
+static int add(int a, int b) { return a + b; }
+int main(void) {
+    int *p = malloc(4);
+    int *q = malloc(4);
+    *p = 5;
+    *q = add(*p, 3);
+    int r = *p * 10 + *q;
+    free(p);
+    free(q);
+    int n = -7;
+    return r + n / 2 * 10 + n % 2;
+}
@@ -0,0 +1,19 @@
+int rax(int x) {
+    return x + 1;
+}
+
+int xmm0(int x) {
+    return x * 2;
+}
+
+int rip(int x) {
+    return x - 3;
+}
+
+int and(int x) {
+    return x + 10;
+}
+
+int main() {
+    return rip(xmm0(rax(and(1))));
+}
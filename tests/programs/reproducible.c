@@ -0,0 +1,19 @@
+int g;
+static int id(int v) { g = g + 1; return v; }
+double scale(double d, int k) { return d * k; }
+int add3(int a, int b, int c) { return a + b * 2 + c * 3; }
+int twice(int a) { return add3(a, a, 1); }
+double half(double d) { return scale(d, 2) / 4.0; }
+long many(long a, long b, long c, long d, long e, long f, long h) { return a + b + c + d + e + f + h * 2; }
+long seven(long a) { return many(a, a, a, a, a, a, a); }
+long six(long a, long b, long c, long d, long e, long f) { return a - b + c - d + e * f; }
+long rotate(long a, long b, long c, long d, long e, long f) { return six(f, a, b, c, d, e); }
+int main(void) {
+    int a = id(1);
+    int b = id(2);
+    int c = id(3);
+    double x = half((double)c);
+    float y = (float)half(x);
+    int t = (a && id(b)) + (c || id(a));
+    return twice(a) + (int)x + (int)y + (int)seven(b) + (int)rotate(a, b, c, t, 5, 6) + g;
+}
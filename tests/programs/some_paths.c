@@ -0,0 +1,6 @@
+int main(void) {
+    int c = 1;
+    int a;
+    c && (a = 7);
+    return a;
+}
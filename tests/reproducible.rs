@@ -0,0 +1,27 @@
+mod common;
+
+// Compiles the same program several times, at every optimization level, and
+// checks that the TAC and assembly printed are the same each time
+#[test]
+fn output_is_reproducible() {
+    for level in ["-O0", "-O1", "-O2"] {
+        let outputs: Vec<Vec<u8>> = (0..5)
+            .map(|_| {
+                let output = common::compiler(&["tests/programs/reproducible.c", level, "-S"]);
+                assert!(
+                    output.status.success(),
+                    "{}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                output.stdout
+            })
+            .collect();
+        for output in outputs.iter().skip(1) {
+            assert!(
+                *output == outputs[0],
+                "Output at {} differs between runs",
+                level
+            );
+        }
+    }
+}
@@ -0,0 +1,21 @@
+mod common;
+
+// A program in the printed form of TAC reads back as the same program, so
+// printing it again gives the text it was read from. The program has
+// globals of every kind, floating point constants that only print one way,
+// integers of other types, stack slots, labels, calls and a static function.
+#[test]
+fn printed_tac_reads_back() {
+    let path = "tests/programs/roundtrip.tac";
+    let text = std::fs::read_to_string(path).unwrap();
+    // -O0 leaves the program as it was read
+    let output = common::compiler(&[path, "-O0", "--interpret"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // What the program writes and its exit status come after it
+    assert_eq!(stdout, format!("{}\n\nexit status: 10\n", text));
+}
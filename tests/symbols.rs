@@ -0,0 +1,18 @@
+mod common;
+
+// GAS reads names like rax and lt as registers and operators in Intel
+// syntax, which functions named after them still assemble with
+#[test]
+fn functions_named_like_registers() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::run("tests/programs/register_names.c", level), 21);
+    }
+}
+
+// Globals read and written through their names get the same treatment
+#[test]
+fn globals_named_like_registers() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::run("tests/programs/global_names.c", level), 20);
+    }
+}
@@ -0,0 +1,36 @@
+mod common;
+
+// A million calls deep, which only fits on the stack as a loop
+#[test]
+fn deep_self_recursion() {
+    for level in ["-O1", "-O2"] {
+        assert_eq!(
+            common::interpret("tests/programs/deep_recursion.tac", level),
+            32
+        );
+        assert_eq!(common::run("tests/programs/deep_recursion.tac", level), 32);
+    }
+}
+
+// Calls to other functions are left to the assembly, which jumps to them
+#[test]
+fn deep_mutual_recursion() {
+    for level in ["-O1", "-O2"] {
+        assert_eq!(common::run("tests/programs/mutual_recursion.tac", level), 1);
+    }
+}
+
+// Without the tail pass every call returns to its caller
+#[test]
+fn tail_jumps_follow_the_pass() {
+    let jumps = |args: &[&str]| {
+        let output = common::compiler(args);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        stdout.matches("jmp .Lsym.").count()
+    };
+    let path = "tests/programs/mutual_recursion.tac";
+    assert_eq!(jumps(&[path, "-O2", "-S"]), 3);
+    assert_eq!(jumps(&[path, "-O0", "-S"]), 0);
+    assert_eq!(jumps(&[path, "-O2", "-fno-tail", "-S"]), 0);
+    assert_eq!(jumps(&[path, "-O0", "-ftail", "-S"]), 3);
+}
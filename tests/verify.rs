@@ -0,0 +1,51 @@
+mod common;
+
+// A local written on only some of the paths to a read is valid C, so it
+// gets past the checks debug builds make after every pass
+#[test]
+fn local_written_on_some_paths() {
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(common::interpret("tests/programs/some_paths.c", level), 7);
+    }
+}
+
+// A variable of the compiler's own that a path reaches unwritten is still
+// an error
+#[test]
+fn temporary_read_before_written() {
+    let output = common::compiler(&["tests/programs/undefined.tac", "-O0", "--interpret"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("%1 is read before it is written"),
+        "{}",
+        stderr
+    );
+}
+
+// A variable has one type, so a move to another type needs another variable
+#[test]
+fn variable_with_two_types() {
+    let output = common::compiler(&["tests/programs/two_types.tac", "-O0", "--interpret"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("%0:u8 is also used as %0:i16"),
+        "{}",
+        stderr
+    );
+}
+
+// A stack slot gives its address to moves, loads and stores, and nothing
+// else can compute with it
+#[test]
+fn stack_slot_as_operand() {
+    let output = common::compiler(&["tests/programs/wrong_kind.tac", "-O0", "--interpret"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Operands of the wrong kind in 'add %0, #0'"),
+        "{}",
+        stderr
+    );
+}